@@ -0,0 +1,213 @@
+use crate::card::{Card, CardSet, CardSuit, CardValue};
+use crate::rules::RuleSet;
+use crate::score::{self, PlayKind, PlayedCards};
+
+// What a hand is hoping to draw.
+//
+// "Unseen" is every card the player can't see: the stock plus everyone else's
+// hands. From the player's point of view any unseen card is as likely as any
+// other to be the next one drawn, which is what makes the hypergeometric math
+// below work. An "out" is an unseen card that would let the hand make a new
+// meld or lay something off that it can't right now.
+
+pub struct PotentialMeld {
+    pub kind: PlayKind,
+    // Cards already in hand that would go in with the out. Empty for a lay-off
+    // of just the drawn card
+    pub cards_in_hand: CardSet,
+    // Drawing any one of these finishes it
+    pub outs: CardSet,
+}
+
+pub struct HandAnalysis {
+    pub unseen: CardSet,
+    pub potential_melds: Vec<PotentialMeld>,
+    // Every card that's an out for at least one potential meld
+    pub all_outs: CardSet,
+}
+
+impl HandAnalysis {
+    pub fn num_unseen(&self) -> usize {
+        self.unseen.as_ordered_list().len()
+    }
+
+    // Chance of drawing at least one of the meld's outs in the next `draws` cards
+    pub fn meld_probability(&self, meld: &PotentialMeld, draws: usize) -> f64 {
+        let num_outs = meld.outs.as_ordered_list().len();
+        prob_at_least_one(num_outs, self.num_unseen(), draws)
+    }
+
+    // Chance of drawing at least one card that helps somehow
+    pub fn any_out_probability(&self, draws: usize) -> f64 {
+        let num_outs = self.all_outs.as_ordered_list().len();
+        prob_at_least_one(num_outs, self.num_unseen(), draws)
+    }
+}
+
+// 52 minus everything visible: the hand, the discard pile and the table
+pub fn unseen_cards(hand: &CardSet, discard_pile: &[Card], played_cards: &PlayedCards) -> CardSet {
+    let mut unseen = CardSet::new();
+    for suit in CardSuit::iter() {
+        for value in CardValue::iter() {
+            let card = Card { suit, value };
+            let is_visible = hand.contains(&card)
+                || discard_pile.contains(&card)
+                || played_cards.straight_flush_played.contains(&card)
+                || played_cards.multiple_played.contains(&card)
+                || played_cards.straight_flush_wilds.iter().any(|w| w.wild == Some(card))
+                || played_cards.multiple_wilds.iter().any(|w| w.wild == Some(card));
+            if !is_visible {
+                unseen.add(&card);
+            }
+        }
+    }
+    unseen
+}
+
+pub fn analyze(
+    hand: &CardSet,
+    discard_pile: &[Card],
+    played_cards: &PlayedCards,
+    rules: &RuleSet,
+) -> HandAnalysis {
+    let unseen = unseen_cards(hand, discard_pile, played_cards);
+    let mut potential_melds: Vec<PotentialMeld> = vec![];
+    let mut all_outs = CardSet::new();
+
+    // Pretend each unseen card got drawn and see what new plays use it. The
+    // discard pile is left out since that's a different decision (digging)
+    for out in unseen.as_ordered_list().iter() {
+        let mut new_hand = *hand;
+        new_hand.add(out);
+
+        let plays = score::all_possible_plays(&new_hand, &[], played_cards, rules);
+        for play in plays.into_iter() {
+            if !play.cards_used.contains(out) {
+                continue;
+            }
+
+            let mut cards_in_hand = play.cards_used;
+            cards_in_hand.remove(out);
+
+            let existing = potential_melds.iter_mut().find(|meld| {
+                meld.kind == play.kind
+                    && meld.cards_in_hand.as_ordered_list() == cards_in_hand.as_ordered_list()
+            });
+            match existing {
+                Some(meld) => {
+                    if !meld.outs.contains(out) {
+                        meld.outs.add(out);
+                    }
+                },
+                None => {
+                    let mut outs = CardSet::new();
+                    outs.add(out);
+                    potential_melds.push(PotentialMeld { kind: play.kind, cards_in_hand, outs });
+                },
+            }
+
+            if !all_outs.contains(out) {
+                all_outs.add(out);
+            }
+        }
+    }
+
+    HandAnalysis { unseen, potential_melds, all_outs }
+}
+
+// Hypergeometric: chance that `draws` cards taken from `num_unseen` without
+// replacement include at least one of `num_outs`.
+// 1 - C(unseen - outs, draws) / C(unseen, draws), multiplied out one draw at a
+// time so nothing overflows. More outs than unseen cards counts as every
+// unseen card being an out
+pub fn prob_at_least_one(num_outs: usize, num_unseen: usize, draws: usize) -> f64 {
+    let num_outs = num_outs.min(num_unseen);
+    if num_outs == 0 || draws == 0 {
+        return 0.0;
+    }
+    if draws > num_unseen - num_outs {
+        return 1.0;
+    }
+
+    let mut prob_none = 1.0;
+    for i in 0..draws {
+        let non_outs_left = (num_unseen - num_outs - i) as f64;
+        let cards_left = (num_unseen - i) as f64;
+        prob_none *= non_outs_left / cards_left;
+    }
+    1.0 - prob_none
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card_list(s: &str) -> Vec<Card> {
+        s.split_whitespace().map(|c| c.parse().unwrap()).collect()
+    }
+
+    fn cards(s: &str) -> CardSet {
+        CardSet::from_vec(&card_list(s))
+    }
+
+    #[test]
+    fn no_outs_or_no_draws_never_hit() {
+        assert_eq!(prob_at_least_one(0, 10, 3), 0.0);
+        assert_eq!(prob_at_least_one(3, 10, 0), 0.0);
+    }
+
+    #[test]
+    fn matches_the_hypergeometric_odds() {
+        assert!((prob_at_least_one(1, 10, 1) - 0.1).abs() < 1e-12);
+        // Missing both outs drawing 2 of 5 is 3/5 * 2/4
+        assert!((prob_at_least_one(2, 5, 2) - 0.7).abs() < 1e-12);
+    }
+
+    #[test]
+    fn more_outs_than_unseen_cards_is_clamped() {
+        assert_eq!(prob_at_least_one(5, 3, 1), 1.0);
+        assert_eq!(prob_at_least_one(5, 0, 1), 0.0);
+    }
+
+    #[test]
+    fn unseen_is_everything_not_in_hand_the_pile_or_on_the_table() {
+        let rules = RuleSet::default();
+        let mut played_cards = PlayedCards::default();
+        let run = score::all_possible_plays(&cards("9:C 10:C J:C"), &[], &played_cards, &rules);
+        played_cards.add_play(&run[0]);
+
+        let unseen = unseen_cards(&cards("A:S 2:D"), &card_list("K:H 4:S"), &played_cards);
+        assert_eq!(unseen.len(), 52 - 2 - 2 - 3);
+        for card in card_list("A:S 2:D K:H 4:S 9:C 10:C J:C").iter() {
+            assert!(!unseen.contains(card));
+        }
+        assert!(unseen.contains(&"Q:C".parse().unwrap()));
+    }
+
+    #[test]
+    fn outs_finish_runs_and_sets() {
+        let analysis = analyze(&cards("5:H 6:H K:S K:D"), &[], &PlayedCards::default(), &RuleSet::default());
+        assert!(analysis.all_outs == cards("4:H 7:H K:H K:C"));
+        assert_eq!(analysis.potential_melds.len(), 2);
+
+        let run = analysis.potential_melds.iter().find(|meld| matches!(meld.kind, PlayKind::StraightFlush { .. })).unwrap();
+        assert!(run.cards_in_hand == cards("5:H 6:H"));
+        assert!(run.outs == cards("4:H 7:H"));
+        let set = analysis.potential_melds.iter().find(|meld| meld.kind == PlayKind::Multiple).unwrap();
+        assert!(set.outs == cards("K:H K:C"));
+    }
+
+    #[test]
+    fn outs_already_seen_dont_count() {
+        let analysis = analyze(&cards("5:H 6:H"), &card_list("7:H"), &PlayedCards::default(), &RuleSet::default());
+        assert!(analysis.all_outs == cards("4:H"));
+        assert_eq!(analysis.num_unseen(), 49);
+        assert!((analysis.any_out_probability(1) - 1.0 / 49.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn drawing_more_than_the_non_outs_is_certain() {
+        assert_eq!(prob_at_least_one(4, 10, 7), 1.0);
+        assert_eq!(prob_at_least_one(4, 10, 10), 1.0);
+    }
+}
@@ -0,0 +1,70 @@
+use rummy_sim::json;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+// Tiny local server so other tools can ask the engine about positions
+//
+// Usage: server [address]   (defaults to 127.0.0.1:8080, port 0 picks a free one)
+//
+// Routes:
+// POST /plays      body is a json::Position, responds with the list of plays
+// POST /validate   body is a json::Position, responds with whether it could happen
+// POST /simulate   body is a json::SimulationRequest, responds with the report
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+
+fn respond(request: Request, status: u16, body: String) {
+    let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+    let response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header);
+    if let Err(e) = request.respond(response) {
+        eprintln!("Failed to send response: {}", e);
+    }
+}
+
+fn error_body(msg: &str) -> String {
+    serde_json::json!({ "error": msg }).to_string()
+}
+
+fn handle(mut request: Request) {
+    let mut body = String::new();
+    if let Err(e) = request.as_reader().read_to_string(&mut body) {
+        respond(request, 400, error_body(&e.to_string()));
+        return;
+    }
+
+    let route: fn(&str) -> Result<String, String> = match request.url() {
+        "/plays" => json::plays_for_position,
+        "/validate" => json::validate_position,
+        "/simulate" => json::simulate,
+        _ => {
+            respond(request, 404, error_body("Not found"));
+            return;
+        },
+    };
+    if *request.method() != Method::Post {
+        respond(request, 405, error_body("Use POST"));
+        return;
+    }
+    match route(&body) {
+        Ok(response) => respond(request, 200, response),
+        Err(e) => respond(request, 400, error_body(&e)),
+    }
+}
+
+fn main() {
+    let address = std::env::args().nth(1).unwrap_or(DEFAULT_ADDRESS.to_string());
+    let server = match Server::http(&address) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("Couldn't listen on {}: {}", address, e);
+            std::process::exit(1);
+        }
+    };
+
+    // The real address, in case the port was picked for us
+    println!("Listening on http://{}", server.server_addr());
+    for request in server.incoming_requests() {
+        handle(request);
+    }
+}
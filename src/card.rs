@@ -4,7 +4,10 @@ pub const NUM_CARD_VALUES: usize = 13;
 pub const NUM_SUITS: usize = 4;
 pub const NUM_POSSIBLE_CARDS: usize = NUM_CARD_VALUES * NUM_SUITS;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub const JOKER_STR: &str = "Joker";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CardValue {
     King,
     Queen,
@@ -88,7 +91,8 @@ impl CardValue {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CardSuit {
     Spades,
     Hearts,
@@ -125,15 +129,16 @@ impl CardSuit {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Card {
     pub suit: CardSuit,
     pub value: CardValue,
 }
 
 
-impl Card {
-    pub fn to_string(&self) -> String {
+impl fmt::Display for Card {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let suit_str = match self.suit {
             CardSuit::Spades => "S",
             CardSuit::Hearts => "H",
@@ -156,34 +161,65 @@ impl Card {
             Queen => "Q",
             King => "K",
         };
-        format!("{}:{}", value_str, suit_str)
+        write!(f, "{}:{}", value_str, suit_str)
     }
 }
 
-impl fmt::Display for Card {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.to_string())
+// Parses the same "value:suit" form that Display writes, e.g. "10:H" or "A:S"
+impl std::str::FromStr for Card {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (value_str, suit_str) = s.split_once(':')
+            .ok_or(format!("Card should look like \"A:S\", got \"{}\"", s))?;
+
+        let suit = match suit_str {
+            "S" => CardSuit::Spades,
+            "H" => CardSuit::Hearts,
+            "C" => CardSuit::Clubs,
+            "D" => CardSuit::Diamonds,
+            _ => return Err(format!("Invalid suit: {}", suit_str)),
+        };
+        use CardValue::*;
+        let value = match value_str {
+            "A" => Ace,
+            "2" => Two,
+            "3" => Three,
+            "4" => Four,
+            "5" => Five,
+            "6" => Six,
+            "7" => Seven,
+            "8" => Eight,
+            "9" => Nine,
+            "10" => Ten,
+            "J" => Jack,
+            "Q" => Queen,
+            "K" => King,
+            _ => return Err(format!("Invalid card value: {}", value_str)),
+        };
+        Ok(Card { suit, value })
     }
 }
 
-#[derive(Clone)]
+// One bit per card, suit-major with aces low, same order as as_ordered_list.
+// Small enough to copy around freely, which the search bots do a lot of
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct CardSet {
-    map: [[bool; NUM_POSSIBLE_CARDS]; NUM_SUITS],
+    bits: u64,
+
+    // Jokers aren't any particular suit or value so they just get counted.
+    // They don't show up in as_ordered_list
+    jokers: usize,
 }
 
 impl CardSet {
     pub fn as_ordered_list(&self) -> Vec<Card> {
         let mut v = vec![];
-        for suit in 0..NUM_SUITS {
-            for value in 0..NUM_POSSIBLE_CARDS {
-                if self.map[suit][value] {
-                    let c = Card {
-                        suit: CardSuit::from_index(suit),
-                        value: CardValue::from_index(value),
-                    };
-                    v.push(c);
-                }
-            }
+        let mut bits = self.bits;
+        while bits != 0 {
+            let bit = bits.trailing_zeros() as usize;
+            v.push(card_of_bit(bit));
+            bits &= bits - 1;
         }
         v
     }
@@ -192,28 +228,33 @@ impl CardSet {
 impl fmt::Display for CardSet {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let card_list = self.as_ordered_list();
-        let str_list: Vec<String> = card_list
+        let mut str_list: Vec<String> = card_list
             .into_iter()
             .map(|c| c.to_string())
             .collect();
+        for _ in 0..self.jokers {
+            str_list.push(JOKER_STR.to_string());
+        }
         write!(f, "{:?}", str_list)
     }
 }
 
+impl Default for CardSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CardSet {
     pub fn new() -> Self {
-        let map = [[false; NUM_POSSIBLE_CARDS]; NUM_SUITS];
-        CardSet { map }
+        CardSet { bits: 0, jokers: 0 }
     }
 
     fn set_value(&mut self, card: &Card, b: bool) -> Result<(), ()> {
-        let suit_index = card.suit.index();
-        let value_index = card.value.index();
-        let old_value = self.map[suit_index][value_index];
-        if old_value == b {
+        if self.contains(card) == b {
             Err(())
         } else {
-            self.map[suit_index][value_index] = b;
+            self.bits ^= 1 << bit_of(card);
             Ok(())
         }
     }
@@ -233,16 +274,55 @@ impl CardSet {
     }
 
     pub fn contains(&self, card: &Card) -> bool {
-        let suit_index = card.suit.index();
-        let value_index = card.value.index();
-        self.map[suit_index][value_index]
+        self.bits & (1 << bit_of(card)) != 0
+    }
+
+    pub fn add_joker(&mut self) {
+        self.jokers += 1;
+    }
+
+    pub fn remove_joker(&mut self) {
+        if self.jokers == 0 {
+            panic!("Removing a joker from a set without one: {}", self);
+        }
+        self.jokers -= 1;
+    }
+
+    pub fn num_jokers(&self) -> usize {
+        self.jokers
+    }
+
+    // Real cards plus jokers
+    pub fn len(&self) -> usize {
+        self.bits.count_ones() as usize + self.jokers
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // The real cards as bits (jokers aren't in here)
+    pub fn bits(&self) -> u64 {
+        self.bits
+    }
+
+    pub fn from_bits(bits: u64) -> CardSet {
+        if bits >> NUM_POSSIBLE_CARDS != 0 {
+            panic!("Invalid card set bits: {:#x}", bits);
+        }
+        CardSet { bits, jokers: 0 }
+    }
+
+    pub fn union(&self, other: &CardSet) -> CardSet {
+        CardSet { bits: self.bits | other.bits, jokers: self.jokers + other.jokers }
     }
 
-    pub fn inner_map(&self) -> &[[bool; NUM_POSSIBLE_CARDS]; NUM_SUITS] {
-        &self.map
+    // Real cards only, jokers stay with self
+    pub fn difference(&self, other: &CardSet) -> CardSet {
+        CardSet { bits: self.bits & !other.bits, jokers: self.jokers }
     }
 
-    pub fn from_vec(v: &Vec<Card>) -> CardSet {
+    pub fn from_vec(v: &[Card]) -> CardSet {
         let mut card_set = CardSet::new();
         for card in v.iter() {
             card_set.add(card);
@@ -251,6 +331,219 @@ impl CardSet {
     }
 }
 
+// Spades, Hearts, Clubs, Diamonds, then ace to king within each suit
+pub fn bit_of(card: &Card) -> usize {
+    card.suit.index() * NUM_CARD_VALUES + card.value.index()
+}
+
+pub fn card_of_bit(bit: usize) -> Card {
+    Card {
+        suit: CardSuit::from_index(bit / NUM_CARD_VALUES),
+        value: CardValue::from_index(bit % NUM_CARD_VALUES),
+    }
+}
+
+// Like CardSet but can hold more than one of the same card, for games that
+// shuffle a few decks together
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct CardMultiset {
+    // Small so the multisets stay cheap to copy and hash
+    counts: [[u8; NUM_CARD_VALUES]; NUM_SUITS],
+    jokers: usize,
+}
+
+impl Default for CardMultiset {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CardMultiset {
+    pub fn new() -> Self {
+        CardMultiset { counts: [[0; NUM_CARD_VALUES]; NUM_SUITS], jokers: 0 }
+    }
+
+    pub fn add(&mut self, card: &Card) {
+        self.counts[card.suit.index()][card.value.index()] += 1;
+    }
+
+    pub fn remove(&mut self, card: &Card) {
+        let count = &mut self.counts[card.suit.index()][card.value.index()];
+        if *count == 0 {
+            panic!("Removing card {} from a multiset without it: {}", card, self);
+        }
+        *count -= 1;
+    }
+
+    pub fn count(&self, card: &Card) -> usize {
+        self.counts[card.suit.index()][card.value.index()] as usize
+    }
+
+    pub fn contains(&self, card: &Card) -> bool {
+        self.count(card) > 0
+    }
+
+    pub fn add_joker(&mut self) {
+        self.jokers += 1;
+    }
+
+    pub fn remove_joker(&mut self) {
+        if self.jokers == 0 {
+            panic!("Removing a joker from a multiset without one: {}", self);
+        }
+        self.jokers -= 1;
+    }
+
+    pub fn num_jokers(&self) -> usize {
+        self.jokers
+    }
+
+    // Every copy, so duplicates show up more than once. No jokers
+    pub fn as_ordered_list(&self) -> Vec<Card> {
+        let mut v = vec![];
+        for suit in CardSuit::iter() {
+            for value in CardValue::iter() {
+                let card = Card { suit, value };
+                for _ in 0..self.count(&card) {
+                    v.push(card);
+                }
+            }
+        }
+        v
+    }
+
+    pub fn len(&self) -> usize {
+        self.counts.iter().flatten().map(|count| *count as usize).sum::<usize>() + self.jokers
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // One of each card there's at least one of (jokers included)
+    pub fn distinct(&self) -> CardSet {
+        let mut set = CardSet::new();
+        for suit in CardSuit::iter() {
+            for value in CardValue::iter() {
+                let card = Card { suit, value };
+                if self.contains(&card) {
+                    set.add(&card);
+                }
+            }
+        }
+        set.jokers = self.jokers;
+        set
+    }
+
+    pub fn from_vec(v: &[Card]) -> CardMultiset {
+        let mut multiset = CardMultiset::new();
+        for card in v.iter() {
+            multiset.add(card);
+        }
+        multiset
+    }
+}
+
+impl From<&CardSet> for CardMultiset {
+    fn from(set: &CardSet) -> Self {
+        let mut multiset = CardMultiset::from_vec(&set.as_ordered_list());
+        multiset.jokers = set.jokers;
+        multiset
+    }
+}
+
+impl fmt::Display for CardMultiset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut str_list: Vec<String> = self.as_ordered_list()
+            .into_iter()
+            .map(|c| c.to_string())
+            .collect();
+        for _ in 0..self.jokers {
+            str_list.push(JOKER_STR.to_string());
+        }
+        write!(f, "{:?}", str_list)
+    }
+}
+
+// Sets go over the wire as a plain list of cards, with "Joker" for each joker
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+enum SetEntry {
+    Card(Card),
+    Joker(String),
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for CardSet {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut entries: Vec<SetEntry> = self.as_ordered_list()
+            .into_iter()
+            .map(SetEntry::Card)
+            .collect();
+        for _ in 0..self.jokers {
+            entries.push(SetEntry::Joker(JOKER_STR.to_string()));
+        }
+        entries.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for CardSet {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries = Vec::<SetEntry>::deserialize(deserializer)?;
+        let mut card_set = CardSet::new();
+        for entry in entries.iter() {
+            match entry {
+                SetEntry::Card(card) => {
+                    if card_set.set_value(card, true).is_err() {
+                        let msg = format!("Card {} appears twice in set", card);
+                        return Err(serde::de::Error::custom(msg));
+                    }
+                },
+                SetEntry::Joker(s) if s == JOKER_STR => card_set.add_joker(),
+                SetEntry::Joker(s) => {
+                    let msg = format!("Expected a card or \"{}\", got \"{}\"", JOKER_STR, s);
+                    return Err(serde::de::Error::custom(msg));
+                },
+            }
+        }
+        Ok(card_set)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for CardMultiset {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut entries: Vec<SetEntry> = self.as_ordered_list()
+            .into_iter()
+            .map(SetEntry::Card)
+            .collect();
+        for _ in 0..self.jokers {
+            entries.push(SetEntry::Joker(JOKER_STR.to_string()));
+        }
+        entries.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for CardMultiset {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries = Vec::<SetEntry>::deserialize(deserializer)?;
+        let mut multiset = CardMultiset::new();
+        for entry in entries.iter() {
+            match entry {
+                SetEntry::Card(card) => multiset.add(card),
+                SetEntry::Joker(s) if s == JOKER_STR => multiset.add_joker(),
+                SetEntry::Joker(s) => {
+                    let msg = format!("Expected a card or \"{}\", got \"{}\"", JOKER_STR, s);
+                    return Err(serde::de::Error::custom(msg));
+                },
+            }
+        }
+        Ok(multiset)
+    }
+}
 
 /*
 impl Card {
@@ -279,3 +572,76 @@ impl Card {
     }
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(s: &str) -> Card {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn multisets_count_every_copy() {
+        let mut hand = CardMultiset::new();
+        hand.add(&card("7:H"));
+        hand.add(&card("7:H"));
+        hand.add(&card("K:S"));
+        hand.add_joker();
+        assert_eq!(hand.count(&card("7:H")), 2);
+        assert_eq!(hand.len(), 4);
+        assert_eq!(hand.as_ordered_list().len(), 3);
+
+        hand.remove(&card("7:H"));
+        assert_eq!(hand.count(&card("7:H")), 1);
+        assert!(hand.contains(&card("7:H")));
+        hand.remove(&card("7:H"));
+        assert!(!hand.contains(&card("7:H")));
+        assert_eq!(hand.len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "without it")]
+    fn removing_a_missing_copy_panics() {
+        let mut hand = CardMultiset::from_vec(&[card("7:H")]);
+        hand.remove(&card("7:H"));
+        hand.remove(&card("7:H"));
+    }
+
+    #[test]
+    fn distinct_keeps_one_of_each_and_the_jokers() {
+        let mut hand = CardMultiset::from_vec(&[card("7:H"), card("7:H"), card("2:C")]);
+        hand.add_joker();
+        hand.add_joker();
+        let set = hand.distinct();
+        let mut expected = CardSet::from_vec(&[card("7:H"), card("2:C")]);
+        expected.add_joker();
+        expected.add_joker();
+        assert!(set == expected);
+        assert!(CardMultiset::from(&set) != hand);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn multisets_go_over_the_wire_as_card_lists() {
+        let mut hand = CardMultiset::from_vec(&[card("7:H"), card("7:H")]);
+        hand.add_joker();
+        let json = serde_json::to_string(&hand).unwrap();
+        assert!(json.ends_with(",\"Joker\"]"));
+        let back: CardMultiset = serde_json::from_str(&json).unwrap();
+        assert!(back == hand);
+        assert!(serde_json::from_str::<CardMultiset>("[\"Jester\"]").is_err());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn sets_reject_repeats_but_take_jokers() {
+        let json = serde_json::to_string(&vec![card("7:H"), card("7:H")]).unwrap();
+        assert!(serde_json::from_str::<CardSet>(&json).is_err());
+        assert!(serde_json::from_str::<CardMultiset>(&json).is_ok());
+
+        let set: CardSet = serde_json::from_str("[\"Joker\", \"Joker\"]").unwrap();
+        assert_eq!(set.num_jokers(), 2);
+        assert!(set.as_ordered_list().is_empty());
+    }
+}
@@ -0,0 +1,239 @@
+use crate::card::{Card, CardMultiset};
+use crate::deck::{Deck, DeckCard};
+use crate::rules::RuleSet;
+use crate::score::{self, Play, PlayKind, PlayedCards};
+
+// Contract Rummy (Shanghai)
+//
+// Each round has a contract, some number of sets and runs, and nobody can lay
+// anything down until they can make the whole thing at once. Runs have to be
+// 4+ here instead of 3+. Two decks (plus jokers) get shuffled together.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Contract {
+    pub sets: usize,
+    pub runs: usize,
+}
+
+pub const SHANGHAI_CONTRACTS: [Contract; 7] = [
+    Contract { sets: 2, runs: 0 },
+    Contract { sets: 1, runs: 1 },
+    Contract { sets: 0, runs: 2 },
+    Contract { sets: 3, runs: 0 },
+    Contract { sets: 2, runs: 1 },
+    Contract { sets: 1, runs: 2 },
+    Contract { sets: 0, runs: 3 },
+];
+
+pub const SHANGHAI_HAND_SIZES: [usize; 7] = [10, 10, 10, 10, 12, 12, 12];
+
+pub const SHANGHAI_NUM_DECKS: usize = 2;
+pub const SHANGHAI_JOKERS_PER_DECK: usize = 2;
+
+pub fn rules() -> RuleSet {
+    RuleSet {
+        min_run_size: 4,
+        ..RuleSet::default()
+    }
+}
+
+// Finds melds that make the contract out of the hand (plus the card just
+// drawn, if there is one), or None if it can't be done. No card gets used
+// in two melds, though with two decks two copies of a card can each go in one
+pub fn check_contract(
+    hand: &CardMultiset,
+    drawn: Option<&Card>,
+    contract: &Contract,
+    rules: &RuleSet,
+) -> Option<Vec<Play>> {
+    let mut hand = hand.clone();
+    if let Some(card) = drawn {
+        hand.add(card);
+    }
+
+    let candidates = score::all_possible_plays_multi(&hand, &[], &PlayedCards::default(), rules);
+    let (sets, runs): (Vec<Play>, Vec<Play>) = candidates
+        .into_iter()
+        .partition(|play| matches!(play.kind, PlayKind::Multiple));
+
+    let mut search = Search {
+        set_needs: sets.iter().map(cards_needed).collect(),
+        run_needs: runs.iter().map(cards_needed).collect(),
+        remaining: hand,
+        chosen_sets: vec![],
+        chosen_runs: vec![],
+    };
+    if !search.search(contract.sets, contract.runs, 0, 0) {
+        return None;
+    }
+
+    let mut melds = vec![];
+    for (i, play) in sets.into_iter().enumerate() {
+        if search.chosen_sets.contains(&i) {
+            melds.push(play);
+        }
+    }
+    for (i, play) in runs.into_iter().enumerate() {
+        if search.chosen_runs.contains(&i) {
+            melds.push(play);
+        }
+    }
+    Some(melds)
+}
+
+// The real cards in a play and how many jokers it needs
+fn cards_needed(play: &Play) -> (Vec<Card>, usize) {
+    let jokers = play.wilds.iter().filter(|w| w.wild.is_none()).count();
+    (play.cards_used.as_ordered_list(), jokers)
+}
+
+struct Search {
+    set_needs: Vec<(Vec<Card>, usize)>,
+    run_needs: Vec<(Vec<Card>, usize)>,
+    remaining: CardMultiset,
+    chosen_sets: Vec<usize>,
+    chosen_runs: Vec<usize>,
+}
+
+impl Search {
+    // Picks candidates in increasing order so the same combination doesn't get
+    // tried once for every order it could be picked in
+    fn search(
+        &mut self,
+        sets_needed: usize,
+        runs_needed: usize,
+        next_set: usize,
+        next_run: usize,
+    ) -> bool {
+        if sets_needed > 0 {
+            for i in next_set..self.set_needs.len() {
+                let needs = self.set_needs[i].clone();
+                if !self.take(&needs) {
+                    continue;
+                }
+                self.chosen_sets.push(i);
+                if self.search(sets_needed - 1, runs_needed, i + 1, next_run) {
+                    return true;
+                }
+                self.chosen_sets.pop();
+                self.give_back(&needs);
+            }
+            return false;
+        }
+
+        if runs_needed > 0 {
+            for i in next_run..self.run_needs.len() {
+                let needs = self.run_needs[i].clone();
+                if !self.take(&needs) {
+                    continue;
+                }
+                self.chosen_runs.push(i);
+                if self.search(0, runs_needed - 1, next_set, i + 1) {
+                    return true;
+                }
+                self.chosen_runs.pop();
+                self.give_back(&needs);
+            }
+            return false;
+        }
+
+        true
+    }
+
+    // Takes the play's cards (and jokers) out of what's left if they're all there
+    fn take(&mut self, cards: &(Vec<Card>, usize)) -> bool {
+        let (card_list, jokers) = cards;
+        let all_there = card_list.iter().all(|c| self.remaining.contains(c))
+            && *jokers <= self.remaining.num_jokers();
+        if !all_there {
+            return false;
+        }
+
+        for card in card_list.iter() {
+            self.remaining.remove(card);
+        }
+        for _ in 0..*jokers {
+            self.remaining.remove_joker();
+        }
+        true
+    }
+
+    fn give_back(&mut self, cards: &(Vec<Card>, usize)) {
+        let (card_list, jokers) = cards;
+        for card in card_list.iter() {
+            self.remaining.add(card);
+        }
+        for _ in 0..*jokers {
+            self.remaining.add_joker();
+        }
+    }
+}
+
+// How often a freshly dealt hand plus one draw already makes the contract,
+// over `trials` Shanghai deals (seeded, so runs are repeatable)
+pub fn make_rate(contract: &Contract, hand_size: usize, trials: usize, seed: u64) -> f64 {
+    let rules = rules();
+    let mut made = 0;
+    for trial in 0..trials {
+        let mut deck = Deck::shuffled(
+            SHANGHAI_NUM_DECKS,
+            SHANGHAI_JOKERS_PER_DECK,
+            seed.wrapping_add(trial as u64),
+        );
+        let mut hand = deck.deal(1, hand_size).pop().unwrap();
+
+        let drawn = match deck.draw() {
+            Some(DeckCard::Card(card)) => Some(card),
+            Some(DeckCard::Joker) => {
+                hand.add_joker();
+                None
+            },
+            None => None,
+        };
+
+        if check_contract(&hand, drawn.as_ref(), contract, &rules).is_some() {
+            made += 1;
+        }
+    }
+    made as f64 / trials as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hand(s: &str) -> CardMultiset {
+        let cards: Vec<Card> = s.split_whitespace().map(|c| c.parse().unwrap()).collect();
+        CardMultiset::from_vec(&cards)
+    }
+
+    #[test]
+    fn two_sets_make_the_first_contract() {
+        let contract = Contract { sets: 2, runs: 0 };
+        let plays = check_contract(&hand("7:H 7:S 7:C 9:D 9:S 9:H 2:C"), None, &contract, &rules()).unwrap();
+        assert_eq!(plays.len(), 2);
+        assert!(plays.iter().all(|play| matches!(play.kind, PlayKind::Multiple)));
+    }
+
+    #[test]
+    fn the_drawn_card_counts() {
+        let contract = Contract { sets: 2, runs: 0 };
+        let cards = hand("7:H 7:S 7:C 9:D 9:S 2:C");
+        assert!(check_contract(&cards, None, &contract, &rules()).is_none());
+        assert!(check_contract(&cards, Some(&"9:H".parse().unwrap()), &contract, &rules()).is_some());
+    }
+
+    #[test]
+    fn two_copies_can_go_in_different_sets() {
+        let contract = Contract { sets: 2, runs: 0 };
+        let plays = check_contract(&hand("7:H 7:H 7:S 7:C 7:C 7:D"), None, &contract, &rules()).unwrap();
+        assert_eq!(plays.len(), 2);
+    }
+
+    #[test]
+    fn runs_need_four_cards() {
+        let contract = Contract { sets: 0, runs: 1 };
+        assert!(check_contract(&hand("5:H 6:H 7:H 8:H"), None, &contract, &rules()).is_some());
+        assert!(check_contract(&hand("5:H 6:H 7:H 9:C"), None, &contract, &rules()).is_none());
+    }
+}
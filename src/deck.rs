@@ -0,0 +1,138 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::card::{Card, CardMultiset, CardSuit, CardValue};
+
+// The stock. Games with lots of players shuffle two (or more) decks together,
+// so the same card can show up more than once.
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeckCard {
+    Card(Card),
+    Joker,
+}
+
+#[derive(Clone)]
+pub struct Deck {
+    // Top of the deck is the end of the vec
+    cards: Vec<DeckCard>,
+}
+
+impl Deck {
+    // In order, not shuffled
+    pub fn new(num_decks: usize, jokers_per_deck: usize) -> Self {
+        let mut cards = vec![];
+        for _ in 0..num_decks {
+            for suit in CardSuit::iter() {
+                for value in CardValue::iter() {
+                    cards.push(DeckCard::Card(Card { suit, value }));
+                }
+            }
+            for _ in 0..jokers_per_deck {
+                cards.push(DeckCard::Joker);
+            }
+        }
+        Deck { cards }
+    }
+
+    // Top card last
+    pub fn from_cards(cards: Vec<DeckCard>) -> Self {
+        Deck { cards }
+    }
+
+    // Same seed, same order, so a deal can be replayed
+    pub fn shuffled(num_decks: usize, jokers_per_deck: usize, seed: u64) -> Self {
+        let mut deck = Deck::new(num_decks, jokers_per_deck);
+        let mut rng = StdRng::seed_from_u64(seed);
+        deck.shuffle(&mut rng);
+        deck
+    }
+
+    pub fn shuffle<R: Rng>(&mut self, rng: &mut R) {
+        self.cards.shuffle(rng);
+    }
+
+    pub fn draw(&mut self) -> Option<DeckCard> {
+        self.cards.pop()
+    }
+
+    // To the bottom of the deck
+    pub fn put_under(&mut self, card: DeckCard) {
+        self.cards.insert(0, card);
+    }
+
+    // One card at a time to each player in turn, like at the table.
+    // Panics if there aren't enough cards
+    pub fn deal(&mut self, num_players: usize, hand_size: usize) -> Vec<CardMultiset> {
+        let mut hands = vec![CardMultiset::new(); num_players];
+        for _ in 0..hand_size {
+            for hand in hands.iter_mut() {
+                match self.draw() {
+                    Some(DeckCard::Card(card)) => hand.add(&card),
+                    Some(DeckCard::Joker) => hand.add_joker(),
+                    None => panic!(
+                        "Ran out of cards dealing {} hands of {}",
+                        num_players, hand_size,
+                    ),
+                }
+            }
+        }
+        hands
+    }
+
+    pub fn len(&self) -> usize {
+        self.cards.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cards.is_empty()
+    }
+
+    // Top card last, same as the discard pile
+    pub fn cards(&self) -> &[DeckCard] {
+        &self.cards
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(deck: &Deck, wanted: DeckCard) -> usize {
+        deck.cards().iter().filter(|card| **card == wanted).count()
+    }
+
+    #[test]
+    fn two_decks_with_jokers() {
+        let deck = Deck::shuffled(2, 2, 7);
+        assert_eq!(deck.len(), 2 * (52 + 2));
+        assert_eq!(count(&deck, DeckCard::Joker), 4);
+        let seven = DeckCard::Card("7:H".parse().unwrap());
+        assert_eq!(count(&deck, seven), 2);
+    }
+
+    #[test]
+    fn same_seed_same_order() {
+        assert!(Deck::shuffled(2, 1, 7).cards() == Deck::shuffled(2, 1, 7).cards());
+        assert!(Deck::shuffled(2, 1, 7).cards() != Deck::shuffled(2, 1, 8).cards());
+    }
+
+    #[test]
+    fn dealing_goes_round_the_table_from_the_top() {
+        let cards = ["A:S", "2:S", "3:S", "4:S", "5:S"]
+            .iter()
+            .map(|card| DeckCard::Card(card.parse().unwrap()))
+            .collect();
+        let mut deck = Deck::from_cards(cards);
+        let hands = deck.deal(2, 2);
+        assert_eq!(hands[0].to_string(), "[\"3:S\", \"5:S\"]");
+        assert_eq!(hands[1].to_string(), "[\"2:S\", \"4:S\"]");
+        assert_eq!(deck.len(), 1);
+
+        deck.put_under(DeckCard::Joker);
+        assert!(deck.draw() == Some(DeckCard::Card("A:S".parse().unwrap())));
+        assert!(deck.draw() == Some(DeckCard::Joker));
+        assert!(deck.draw().is_none());
+    }
+}
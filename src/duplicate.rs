@@ -0,0 +1,262 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::game::{self, GameState, RoundOutcome, Strategy};
+use crate::rules::RuleSet;
+use crate::stats::{self, RunningStats};
+
+// Duplicate, like in bridge: every deal gets played once for every way of
+// seating the players, with exactly the same cards (stock order included,
+// since that comes from the deal's seed). Whoever's better gets the good
+// hands and the bad hands the same number of times, so the luck of the deal
+// mostly cancels out and far fewer deals are needed to tell bots apart.
+//
+// That's n! rounds per deal, so 24 with four players and 720 with six.
+
+pub struct DuplicateReport {
+    pub deals: usize,
+    pub rounds: usize,
+    pub turn_capped_rounds: usize,
+    // Per player, over every round played
+    pub total_scores: Vec<i64>,
+    // Per player, rounds where they had the best score. Split when tied
+    pub round_wins: Vec<f64>,
+    // Per player, their average score over all the seatings of each deal
+    pub deal_scores: Vec<RunningStats>,
+    // differences[a][b] is a's average score on each deal minus b's
+    pub differences: Vec<Vec<RunningStats>>,
+}
+
+impl DuplicateReport {
+    pub fn new(num_players: usize) -> Self {
+        DuplicateReport {
+            deals: 0,
+            rounds: 0,
+            turn_capped_rounds: 0,
+            total_scores: vec![0; num_players],
+            round_wins: vec![0.0; num_players],
+            deal_scores: vec![RunningStats::new(); num_players],
+            differences: vec![vec![RunningStats::new(); num_players]; num_players],
+        }
+    }
+
+    pub fn num_players(&self) -> usize {
+        self.total_scores.len()
+    }
+
+    // Per round
+    pub fn mean_score(&self, player: usize) -> f64 {
+        self.deal_scores[player].mean
+    }
+
+    pub fn mean_score_interval(&self, player: usize, confidence: f64) -> (f64, f64) {
+        self.deal_scores[player].interval(confidence)
+    }
+
+    pub fn round_win_rate(&self, player: usize) -> f64 {
+        self.round_wins[player] / self.rounds as f64
+    }
+
+    pub fn round_win_rate_interval(&self, player: usize, confidence: f64) -> (f64, f64) {
+        stats::wilson_interval(self.round_wins[player], self.rounds, confidence)
+    }
+
+    // How many more points a scores than b per round
+    pub fn difference_interval(&self, a: usize, b: usize, confidence: f64) -> (f64, f64) {
+        self.differences[a][b].interval(confidence)
+    }
+
+    // Scores per player for each seating of one deal
+    fn add_deal(&mut self, rounds: &[(Vec<usize>, GameState)]) {
+        let n = self.num_players();
+        let mut deal_totals = vec![0.0; n];
+        for (seating, state) in rounds.iter() {
+            self.rounds += 1;
+            if state.outcome() == Some(RoundOutcome::TurnCap) {
+                self.turn_capped_rounds += 1;
+            }
+
+            let scores = state.scores();
+            let best = *scores.iter().max().unwrap();
+            let num_best = scores.iter().filter(|s| **s == best).count();
+            for (seat, player) in seating.iter().enumerate() {
+                self.total_scores[*player] += scores[seat] as i64;
+                deal_totals[*player] += scores[seat] as f64;
+                if scores[seat] == best {
+                    self.round_wins[*player] += 1.0 / num_best as f64;
+                }
+            }
+        }
+
+        self.deals += 1;
+        let deal_means: Vec<f64> = deal_totals.iter().map(|total| total / rounds.len() as f64).collect();
+        for a in 0..n {
+            self.deal_scores[a].add(deal_means[a]);
+            for b in 0..n {
+                if a != b {
+                    self.differences[a][b].add(deal_means[a] - deal_means[b]);
+                }
+            }
+        }
+    }
+}
+
+// Plays `num_deals` deals, each once per seating. `make_strategy` gets the
+// player, the seat they're in and the deal's seed, and returns a fresh
+// strategy for that player. Errors if the rules can't be dealt to that many
+// players
+pub fn play_duplicate<F>(
+    num_players: usize,
+    rules: &RuleSet,
+    num_deals: usize,
+    seed: u64,
+    mut make_strategy: F,
+) -> Result<DuplicateReport, String>
+where
+    F: FnMut(usize, usize, u64) -> Box<dyn Strategy>,
+{
+    let seatings = permutations(num_players);
+    let mut seeds = StdRng::seed_from_u64(seed);
+    let mut report = DuplicateReport::new(num_players);
+
+    for _ in 0..num_deals {
+        let deal_seed: u64 = seeds.gen();
+        let deal = GameState::deal(num_players, 0, rules, deal_seed)?;
+
+        let mut rounds = vec![];
+        for seating in seatings.iter() {
+            // seating[seat] is the player sitting there
+            let mut strategies: Vec<Box<dyn Strategy>> = seating
+                .iter()
+                .enumerate()
+                .map(|(seat, player)| make_strategy(*player, seat, deal_seed))
+                .collect();
+            let mut state = deal.clone();
+            game::play_round(&mut state, &mut strategies);
+            rounds.push((seating.clone(), state));
+        }
+        report.add_deal(&rounds);
+    }
+    Ok(report)
+}
+
+// Every ordering of 0..n
+fn permutations(n: usize) -> Vec<Vec<usize>> {
+    if n == 0 {
+        return vec![vec![]];
+    }
+    let mut all = vec![];
+    for shorter in permutations(n - 1) {
+        for position in 0..n {
+            let mut permutation = shorter.clone();
+            permutation.insert(position, n - 1);
+            all.push(permutation);
+        }
+    }
+    all
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::card::{Card, CardMultiset};
+    use crate::game::{Draw, PlayerView};
+    use crate::greedy::GreedyStrategy;
+    use crate::score::Play;
+
+    // (deal seed, seat) and a card or hand
+    type Log = Rc<RefCell<Vec<((u64, usize), String)>>>;
+
+    // Always draws from the stock and throws back what it drew, so every
+    // seating plays out the same. Logs its starting hand and what it drew
+    struct StockWatcher {
+        key: (u64, usize),
+        hand: Option<CardMultiset>,
+        log: Log,
+    }
+
+    impl Strategy for StockWatcher {
+        fn choose_draw(&mut self, view: &PlayerView) -> Draw {
+            if self.hand.is_none() {
+                self.log.borrow_mut().push((self.key, view.hand().to_string()));
+            }
+            self.hand = Some(view.hand().clone());
+            Draw::Stock
+        }
+
+        fn choose_play(&mut self, _view: &PlayerView) -> Option<Play> {
+            None
+        }
+
+        fn choose_discard(&mut self, view: &PlayerView) -> Card {
+            let before = self.hand.as_ref().unwrap();
+            let drawn = view
+                .hand()
+                .as_ordered_list()
+                .into_iter()
+                .find(|card| view.hand().count(card) > before.count(card))
+                .unwrap();
+            self.log.borrow_mut().push((self.key, drawn.to_string()));
+            drawn
+        }
+    }
+
+    fn watcher(key: (u64, usize), log: &Log) -> Box<dyn Strategy> {
+        Box::new(StockWatcher { key, hand: None, log: log.clone() })
+    }
+
+    #[test]
+    fn every_seating_gets_the_same_cards() {
+        let log = Rc::new(RefCell::new(vec![]));
+        let report = play_duplicate(3, &RuleSet::default(), 2, 5, |_, seat, seed| watcher((seed, seat), &log)).unwrap();
+        assert_eq!(report.deals, 2);
+        assert_eq!(report.rounds, 2 * 6);
+
+        // Each seat's hand and every card it drew, in order, once per seating
+        let log = log.borrow();
+        let keys: Vec<(u64, usize)> = log.iter().map(|(key, _)| *key).collect();
+        for key in keys.iter() {
+            let seen: Vec<&String> = log.iter().filter(|(k, _)| k == key).map(|(_, card)| card).collect();
+            assert_eq!(seen.len() % 6, 0);
+            let per_seating = seen.len() / 6;
+            for seating in 1..6 {
+                assert_eq!(seen[..per_seating], seen[seating * per_seating..(seating + 1) * per_seating]);
+            }
+        }
+    }
+
+    #[test]
+    fn results_go_to_players_not_seats() {
+        let log = Rc::new(RefCell::new(vec![]));
+        let report = play_duplicate(2, &RuleSet::default(), 4, 5, |player, seat, seed| {
+            if player == 0 {
+                Box::new(GreedyStrategy)
+            } else {
+                watcher((seed, seat), &log)
+            }
+        })
+        .unwrap();
+        assert_eq!(report.rounds, 8);
+        // The greedy player wins from either seat
+        assert!(report.round_wins[0] > report.round_wins[1]);
+        assert!(report.total_scores[0] > report.total_scores[1]);
+        assert_eq!(report.round_wins.iter().sum::<f64>(), 8.0);
+        assert!(report.differences[0][1].mean > 0.0);
+        assert_eq!(report.differences[0][1].mean, -report.differences[1][0].mean);
+    }
+
+    #[test]
+    fn permutations_are_every_seating() {
+        let all = permutations(3);
+        assert_eq!(all.len(), 6);
+        for permutation in all.iter() {
+            let mut sorted = permutation.clone();
+            sorted.sort();
+            assert_eq!(sorted, vec![0, 1, 2]);
+        }
+        assert!(all.iter().enumerate().all(|(i, p)| !all[..i].contains(p)));
+    }
+}
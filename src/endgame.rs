@@ -0,0 +1,459 @@
+use std::collections::HashMap;
+
+use crate::card::{Card, CardMultiset, CardSet};
+use crate::deck::{Deck, DeckCard};
+use crate::game::{Action, Draw, GameState};
+use crate::score::WildUse;
+
+// Exact search for the end of a round, with every hand face up.
+//
+// Everyone plays to maximise their own margin (their score minus the average
+// of everyone else's). If the stock order is known too, two players is plain
+// minimax and gets alpha-beta. Otherwise every stock draw is a chance node
+// over the cards still in the stock, and the search is expectimax.
+//
+// By default the search goes all the way to the end of the round. Rounds don't
+// have to end by themselves (people can pass the top discard back and forth),
+// but the turn cap ends them eventually, so this is exact but can be slow. A
+// `max_turns` horizon scores the round as it stands after that many turns
+// instead, and the solution says whether it cut anything short.
+//
+// The same plays made in a different order end up in the same position, so
+// positions get cached and each one is only searched once.
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum StockOrder {
+    // Analysis mode: the stock is drawn in the order it's in
+    Known,
+    // Any card left in the stock is as likely as any other to come next
+    Unknown,
+}
+
+#[derive(Clone, Copy)]
+pub struct EndgameConfig {
+    // None searches to the end of the round
+    pub max_turns: Option<usize>,
+    pub stock_order: StockOrder,
+}
+
+impl Default for EndgameConfig {
+    fn default() -> Self {
+        EndgameConfig {
+            max_turns: None,
+            stock_order: StockOrder::Unknown,
+        }
+    }
+}
+
+pub struct Solution {
+    // Expected final scores for the round with best play
+    pub scores: Vec<f64>,
+    // Best actions from here. With an unknown stock this stops at the first
+    // stock draw, since what's best after that depends on the card
+    pub line: Vec<Action>,
+    // Positions searched (not counting cache hits)
+    pub nodes: usize,
+    // False if max_turns stopped the search before the round was over
+    // anywhere, in which case the scores are only an estimate
+    pub exact: bool,
+}
+
+pub fn solve(state: &GameState, config: &EndgameConfig) -> Solution {
+    let mut solver = Solver {
+        last_turn: config.max_turns.map_or(usize::MAX, |max_turns| state.turns + max_turns),
+        stock_order: config.stock_order,
+        nodes: 0,
+        cut_off: false,
+        bounds: HashMap::new(),
+        expected: HashMap::new(),
+    };
+
+    if config.stock_order == StockOrder::Known && state.num_players() == 2 {
+        let line = solver.principal_line(state);
+        let mut end = state.clone();
+        for action in line.iter() {
+            end.apply_action(action);
+        }
+        let scores = end.scores().iter().map(|s| *s as f64).collect();
+        return Solution { scores, line, nodes: solver.nodes, exact: !solver.cut_off };
+    }
+
+    let scores = solver.expectimax(state);
+    let line = solver.line(state, |solver, key| solver.expected.get(key).map(|e| e.1.clone()));
+    Solution { scores, line, nodes: solver.nodes, exact: !solver.cut_off }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Bound {
+    Exact,
+    // The real value is at least this
+    Lower,
+    // The real value is at most this
+    Upper,
+}
+
+struct BoundEntry {
+    value: f64,
+    bound: Bound,
+    // Where the best action was in the position's ordered legal actions,
+    // which are always the same for the same position
+    best: Option<usize>,
+}
+
+struct Solver {
+    last_turn: usize,
+    stock_order: StockOrder,
+    nodes: usize,
+    // Whether the horizon stopped anything before the round was over
+    cut_off: bool,
+    bounds: HashMap<PositionKey, BoundEntry>,
+    expected: HashMap<PositionKey, (Vec<f64>, Option<Action>)>,
+}
+
+impl Solver {
+    fn is_leaf(&self, state: &GameState) -> bool {
+        state.is_over() || state.turns >= self.last_turn
+    }
+
+    // For scoring a leaf
+    fn leaf_scores(&mut self, state: &GameState) -> Vec<i32> {
+        if !state.is_over() {
+            self.cut_off = true;
+        }
+        state.scores()
+    }
+
+    // Negamax from the point of view of whoever's turn it is. A player takes
+    // several actions in a row in one turn, so the sign only flips when the
+    // turn passes
+    fn alpha_beta(&mut self, state: &GameState, mut alpha: f64, mut beta: f64) -> f64 {
+        let player = state.current_player;
+        if self.is_leaf(state) {
+            let scores = self.leaf_scores(state);
+            return (scores[player] - scores[1 - player]) as f64;
+        }
+
+        let key = PositionKey::of(state, self.stock_order);
+        let mut hint = None;
+        if let Some(entry) = self.bounds.get(&key) {
+            hint = entry.best;
+            match entry.bound {
+                Bound::Exact => return entry.value,
+                Bound::Lower => alpha = alpha.max(entry.value),
+                Bound::Upper => beta = beta.min(entry.value),
+            }
+            if alpha >= beta {
+                return entry.value;
+            }
+        }
+        self.nodes += 1;
+
+        let original_alpha = alpha;
+        let mut best_value = f64::NEG_INFINITY;
+        let mut best_action = None;
+        // Whatever was best last time this got searched is likely best again
+        let mut actions: Vec<(usize, Action)> = ordered(state.legal_actions()).into_iter().enumerate().collect();
+        if let Some(index) = hint {
+            let action = actions.remove(index);
+            actions.insert(0, action);
+        }
+        for (index, action) in actions {
+            let mut child = state.clone();
+            child.apply_action(&action);
+
+            let value = if child.current_player == player {
+                self.alpha_beta(&child, alpha, beta)
+            } else {
+                -self.alpha_beta(&child, -beta, -alpha)
+            };
+
+            if value > best_value {
+                best_value = value;
+                best_action = Some(index);
+            }
+            alpha = alpha.max(value);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        let bound = if best_value <= original_alpha {
+            Bound::Upper
+        } else if best_value >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.bounds.insert(key, BoundEntry { value: best_value, bound, best: best_action });
+        best_value
+    }
+
+    fn expectimax(&mut self, state: &GameState) -> Vec<f64> {
+        if self.is_leaf(state) {
+            return self.leaf_scores(state).iter().map(|s| *s as f64).collect();
+        }
+
+        let key = PositionKey::of(state, self.stock_order);
+        if let Some((scores, _)) = self.expected.get(&key) {
+            return scores.clone();
+        }
+        self.nodes += 1;
+
+        let player = state.current_player;
+        let mut best: Option<(Vec<f64>, Action)> = None;
+        for action in ordered(state.legal_actions()) {
+            let scores = match action {
+                Action::Draw(Draw::Stock) if self.stock_order == StockOrder::Unknown => {
+                    self.stock_chance(state)
+                },
+                _ => {
+                    let mut child = state.clone();
+                    child.apply_action(&action);
+                    self.expectimax(&child)
+                },
+            };
+
+            let is_better = match &best {
+                Some((best_scores, _)) => {
+                    expected_margin(&scores, player) > expected_margin(best_scores, player)
+                },
+                None => true,
+            };
+            if is_better {
+                best = Some((scores, action));
+            }
+        }
+
+        let (scores, action) = best.expect("Invalid game state: round isn't over but there's nothing to do");
+        self.expected.insert(key, (scores.clone(), Some(action)));
+        scores
+    }
+
+    // Average over every card that could be on top of the stock
+    fn stock_chance(&mut self, state: &GameState) -> Vec<f64> {
+        // An empty stock here means the discards are about to get reshuffled,
+        // and that's seeded so there's only one way it can go
+        if state.stock.is_empty() {
+            let mut child = state.clone();
+            child.apply_draw(&Draw::Stock);
+            return self.expectimax(&child);
+        }
+
+        let cards = state.stock.cards().to_vec();
+        let mut totals = vec![0.0; state.num_players()];
+        for i in 0..cards.len() {
+            let mut order: Vec<DeckCard> = cards.clone();
+            let drawn = order.remove(i);
+            order.push(drawn);
+
+            let mut child = state.clone();
+            child.stock = Deck::from_cards(order);
+            child.apply_draw(&Draw::Stock);
+            let scores = self.expectimax(&child);
+            for (total, score) in totals.iter_mut().zip(scores.iter()) {
+                *total += score;
+            }
+        }
+        totals.iter().map(|total| total / cards.len() as f64).collect()
+    }
+
+    // Best play for both sides, from here to the end of the search. Cached
+    // alpha-beta entries can be bounds rather than exact values, so each step
+    // searches every option again with a full window rather than trusting
+    // the cached best action. The cache still makes that cheap
+    fn principal_line(&mut self, state: &GameState) -> Vec<Action> {
+        let mut line = vec![];
+        let mut state = state.clone();
+        while !self.is_leaf(&state) {
+            let player = state.current_player;
+            let mut best: Option<(f64, Action)> = None;
+            for action in ordered(state.legal_actions()) {
+                let mut child = state.clone();
+                child.apply_action(&action);
+                let value = if child.current_player == player {
+                    self.alpha_beta(&child, f64::NEG_INFINITY, f64::INFINITY)
+                } else {
+                    -self.alpha_beta(&child, f64::NEG_INFINITY, f64::INFINITY)
+                };
+                if best.as_ref().is_none_or(|(best_value, _)| value > *best_value) {
+                    best = Some((value, action));
+                }
+            }
+            let (_, action) = best.expect("Invalid game state: round isn't over but there's nothing to do");
+            state.apply_action(&action);
+            line.push(action);
+        }
+        line
+    }
+
+    // Follows the cached best actions down from the start
+    fn line<F>(&self, state: &GameState, best_action: F) -> Vec<Action>
+    where
+        F: Fn(&Solver, &PositionKey) -> Option<Option<Action>>,
+    {
+        let mut line = vec![];
+        let mut state = state.clone();
+        while !self.is_leaf(&state) {
+            let action = match best_action(self, &PositionKey::of(&state, self.stock_order)) {
+                Some(Some(action)) => action,
+                _ => break,
+            };
+            let is_chance = matches!(action, Action::Draw(Draw::Stock))
+                && self.stock_order == StockOrder::Unknown;
+            state.apply_action(&action);
+            line.push(action);
+            if is_chance {
+                break;
+            }
+        }
+        line
+    }
+}
+
+// Everything that makes one position different from another
+#[derive(PartialEq, Eq, Hash)]
+struct PositionKey {
+    hands: Vec<CardMultiset>,
+    discard_pile: Vec<Card>,
+    stock: StockKey,
+    straight_flush_played: CardSet,
+    multiple_played: CardSet,
+    straight_flush_wilds: Vec<WildUse>,
+    multiple_wilds: Vec<WildUse>,
+    melded_points: Vec<u32>,
+    current_player: usize,
+    has_drawn: bool,
+    turns: usize,
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum StockKey {
+    Ordered(Vec<DeckCard>),
+    // When the order's unknown only what's in there matters
+    Unordered(CardMultiset),
+}
+
+impl PositionKey {
+    fn of(state: &GameState, stock_order: StockOrder) -> PositionKey {
+        let stock = match stock_order {
+            StockOrder::Known => StockKey::Ordered(state.stock.cards().to_vec()),
+            StockOrder::Unknown => {
+                let mut set = CardMultiset::new();
+                for card in state.stock.cards().iter() {
+                    match card {
+                        DeckCard::Card(card) => set.add(card),
+                        DeckCard::Joker => set.add_joker(),
+                    }
+                }
+                StockKey::Unordered(set)
+            },
+        };
+        PositionKey {
+            hands: state.hands.clone(),
+            discard_pile: state.discard_pile.clone(),
+            stock,
+            straight_flush_played: state.played_cards.straight_flush_played,
+            multiple_played: state.played_cards.multiple_played,
+            straight_flush_wilds: state.played_cards.straight_flush_wilds.clone(),
+            multiple_wilds: state.played_cards.multiple_wilds.clone(),
+            melded_points: state.melded_points.clone(),
+            current_player: state.current_player,
+            has_drawn: state.has_drawn,
+            turns: state.turns,
+        }
+    }
+}
+
+// Same as monte_carlo::margin but for expected scores
+fn expected_margin(scores: &[f64], seat: usize) -> f64 {
+    let others: f64 = scores.iter().enumerate().filter(|(i, _)| *i != seat).map(|(_, s)| s).sum();
+    scores[seat] - others / (scores.len() - 1) as f64
+}
+
+// Plays and digs first since they're usually best, which makes alpha-beta cut
+// more
+fn ordered(actions: Vec<Action>) -> Vec<Action> {
+    let (mut first, rest): (Vec<Action>, Vec<Action>) = actions
+        .into_iter()
+        .partition(|action| matches!(action, Action::Play(_) | Action::Draw(Draw::Dig(_))));
+    first.extend(rest);
+    first
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::RuleSet;
+
+    fn cards(s: &str) -> Vec<Card> {
+        s.split_whitespace().map(|c| c.parse().unwrap()).collect()
+    }
+
+    // Player 0 to draw, with a run to dig for, and a couple of cards left in
+    // the stock (the king on top)
+    fn small_endgame() -> GameState {
+        let mut state = GameState::deal(2, 1, &RuleSet::default(), 0).unwrap();
+        state.hands[0] = CardMultiset::from_vec(&cards("5:H 6:H 9:C"));
+        state.hands[1] = CardMultiset::from_vec(&cards("K:S K:D 2:C"));
+        state.discard_pile = cards("7:H");
+        state.stock = Deck::from_cards(cards("4:D K:H").into_iter().map(DeckCard::Card).collect());
+        state.turn_cap = state.turns + 8;
+        state
+    }
+
+    fn margin(scores: &[f64], player: usize) -> f64 {
+        scores[player] - scores[1 - player]
+    }
+
+    #[test]
+    fn known_stock_scores_match_the_search_value() {
+        let state = small_endgame();
+        let config = EndgameConfig { max_turns: None, stock_order: StockOrder::Known };
+        let solution = solve(&state, &config);
+        assert!(solution.exact);
+
+        let mut solver = Solver {
+            last_turn: usize::MAX,
+            stock_order: StockOrder::Known,
+            nodes: 0,
+            cut_off: false,
+            bounds: HashMap::new(),
+            expected: HashMap::new(),
+        };
+        let value = solver.alpha_beta(&state, f64::NEG_INFINITY, f64::INFINITY);
+        assert_eq!(margin(&solution.scores, 0), value);
+
+        let mut end = state.clone();
+        for action in solution.line.iter() {
+            end.apply_action(action);
+        }
+        assert!(end.is_over());
+        let end_scores: Vec<f64> = end.scores().iter().map(|s| *s as f64).collect();
+        assert_eq!(end_scores, solution.scores);
+    }
+
+    #[test]
+    fn the_best_line_digs_for_the_run() {
+        let config = EndgameConfig { max_turns: None, stock_order: StockOrder::Known };
+        let solution = solve(&small_endgame(), &config);
+        assert!(matches!(solution.line.first(), Some(Action::Draw(Draw::Dig(_)))));
+    }
+
+    #[test]
+    fn a_horizon_makes_the_result_inexact() {
+        let state = small_endgame();
+        for stock_order in [StockOrder::Known, StockOrder::Unknown] {
+            let config = EndgameConfig { max_turns: Some(1), stock_order };
+            assert!(!solve(&state, &config).exact);
+        }
+    }
+
+    #[test]
+    fn unknown_stock_searches_to_the_end() {
+        let config = EndgameConfig { max_turns: None, stock_order: StockOrder::Unknown };
+        let solution = solve(&small_endgame(), &config);
+        assert!(solution.exact);
+        assert_eq!(solution.scores.len(), 2);
+        assert!(!solution.line.is_empty());
+    }
+}
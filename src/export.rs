@@ -0,0 +1,408 @@
+use std::io::{self, Write};
+
+use crate::card::JOKER_STR;
+use crate::game::{GameEvent, GameState, RoundOutcome};
+use crate::score::{Play, PlayKind};
+
+// Writing simulation results out for analysis somewhere else, one row per
+// round and optionally one per turn. Rows get written as each round finishes,
+// so nothing builds up in memory however many games get played. Wrap the
+// writers in a BufWriter.
+//
+// CSV cards look like "10:H", same as Card's Display. Plays are written
+// "StraightFlush[3:H 4:H 5:H Joker]" and separated by ';'. JSON lines (with
+// the json feature) have the same fields, with lists as lists.
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    #[cfg(feature = "json")]
+    JsonLines,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct GameRow {
+    pub match_index: usize,
+    pub round: usize,
+    pub seed: u64,
+    pub dealer: usize,
+    // Per seat
+    pub players: Vec<String>,
+    pub scores: Vec<i32>,
+    pub turns: usize,
+    pub outcome: String,
+    // Seat that went out (or knocked, in Gin), if anyone did
+    pub went_out: Option<usize>,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TurnRow {
+    pub match_index: usize,
+    pub round: usize,
+    pub turn: usize,
+    pub player: usize,
+    // Before drawing
+    pub hand_size: usize,
+    // "stock" or "discard"
+    pub draw: String,
+    // How many cards were taken off the discard pile. 0 for the stock
+    pub dig_depth: usize,
+    pub plays: Vec<PlayRow>,
+    // None if they went out without discarding
+    pub discard: Option<String>,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PlayRow {
+    pub kind: String,
+    pub cards: Vec<String>,
+}
+
+pub struct Exporter<W: Write> {
+    pub format: ExportFormat,
+    // Per seat
+    pub players: Vec<String>,
+    games: W,
+    turns: Option<W>,
+    wrote_headers: bool,
+}
+
+impl<W: Write> Exporter<W> {
+    pub fn new(format: ExportFormat, players: Vec<String>, games: W) -> Self {
+        Exporter { format, players, games, turns: None, wrote_headers: false }
+    }
+
+    // Per turn rows as well, to a separate output
+    pub fn with_turns(mut self, turns: W) -> Self {
+        self.turns = Some(turns);
+        self
+    }
+
+    // Takes a finished round
+    pub fn write_round(&mut self, match_index: usize, round: usize, state: &GameState) -> io::Result<()> {
+        if !self.wrote_headers {
+            self.write_headers()?;
+            self.wrote_headers = true;
+        }
+
+        let game = game_row(match_index, round, &self.players, state);
+        write_game(&mut self.games, self.format, &game)?;
+
+        if let Some(turns) = &mut self.turns {
+            for row in turn_rows(match_index, round, &state.history) {
+                write_turn(turns, self.format, &row)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.games.flush()?;
+        if let Some(turns) = &mut self.turns {
+            turns.flush()?;
+        }
+        Ok(())
+    }
+
+    fn write_headers(&mut self) -> io::Result<()> {
+        if self.format != ExportFormat::Csv {
+            return Ok(());
+        }
+        write!(self.games, "match_index,round,seed,dealer,turns,outcome,went_out")?;
+        for seat in 0..self.players.len() {
+            write!(self.games, ",player_{0},score_{0}", seat)?;
+        }
+        writeln!(self.games)?;
+        if let Some(turns) = &mut self.turns {
+            writeln!(turns, "match_index,round,turn,player,hand_size,draw,dig_depth,plays,discard")?;
+        }
+        Ok(())
+    }
+}
+
+// Takes a finished round
+pub fn game_row(match_index: usize, round: usize, players: &[String], state: &GameState) -> GameRow {
+    let outcome = state.outcome().expect("Invalid game state: exporting an unfinished round");
+    GameRow {
+        match_index,
+        round,
+        seed: state.seed,
+        dealer: state.dealer,
+        players: players.to_vec(),
+        scores: state.scores(),
+        turns: state.turns,
+        outcome: match outcome {
+            RoundOutcome::WentOut(_) => "WentOut",
+            RoundOutcome::Knocked(_) => "Knocked",
+            RoundOutcome::StockExhausted => "StockExhausted",
+            RoundOutcome::TurnCap => "TurnCap",
+        }
+        .to_string(),
+        went_out: match outcome {
+            RoundOutcome::WentOut(seat) | RoundOutcome::Knocked(seat) => Some(seat),
+            _ => None,
+        },
+    }
+}
+
+// Works the turns back out from the round's events
+pub fn turn_rows(match_index: usize, round: usize, history: &[GameEvent]) -> Vec<TurnRow> {
+    let mut rows = vec![];
+    let mut hand_sizes: Vec<usize> = vec![];
+    let mut current: Option<TurnRow> = None;
+
+    for event in history.iter() {
+        match event {
+            GameEvent::RoundStarted { hand_sizes: sizes, .. } => hand_sizes = sizes.clone(),
+            GameEvent::DrewFromStock { player } => {
+                let row = new_turn(match_index, round, rows.len(), *player, hand_sizes[*player], "stock", 0);
+                start_turn(&mut rows, &mut current, row);
+                hand_sizes[*player] += 1;
+            },
+            GameEvent::TookDiscards { player, cards } => {
+                let row = new_turn(match_index, round, rows.len(), *player, hand_sizes[*player], "discard", cards.len());
+                start_turn(&mut rows, &mut current, row);
+                hand_sizes[*player] += cards.len();
+            },
+            GameEvent::Played { player, play } => {
+                hand_sizes[*player] -= play.cards_used.as_ordered_list().len() + num_jokers(play);
+                if let Some(row) = &mut current {
+                    row.plays.push(play_row(play));
+                }
+            },
+            // A knock's discard is the last one of the round
+            GameEvent::Discarded { player, card } | GameEvent::Knocked { player, card } => {
+                hand_sizes[*player] -= 1;
+                if let Some(mut row) = current.take() {
+                    row.discard = Some(card.to_string());
+                    rows.push(row);
+                }
+            },
+            GameEvent::DiscardedJoker { player } => {
+                hand_sizes[*player] -= 1;
+                if let Some(mut row) = current.take() {
+                    row.discard = Some(JOKER_STR.to_string());
+                    rows.push(row);
+                }
+            },
+            GameEvent::StockReshuffled { .. } => {},
+            GameEvent::RoundOver { .. } => {
+                if let Some(row) = current.take() {
+                    rows.push(row);
+                }
+            },
+        }
+    }
+    rows
+}
+
+fn start_turn(rows: &mut Vec<TurnRow>, current: &mut Option<TurnRow>, row: TurnRow) {
+    // Whoever went out without a discard never finished their turn
+    if let Some(unfinished) = current.take() {
+        rows.push(unfinished);
+    }
+    *current = Some(row);
+}
+
+fn new_turn(
+    match_index: usize,
+    round: usize,
+    turn: usize,
+    player: usize,
+    hand_size: usize,
+    draw: &str,
+    dig_depth: usize,
+) -> TurnRow {
+    TurnRow {
+        match_index,
+        round,
+        turn,
+        player,
+        hand_size,
+        draw: draw.to_string(),
+        dig_depth,
+        plays: vec![],
+        discard: None,
+    }
+}
+
+fn play_row(play: &Play) -> PlayRow {
+    let kind = match play.kind {
+        PlayKind::StraightFlush { .. } => "StraightFlush",
+        PlayKind::Multiple => "Multiple",
+    };
+    let mut cards: Vec<String> = play.cards_used.as_ordered_list().iter().map(|c| c.to_string()).collect();
+    for _ in 0..num_jokers(play) {
+        cards.push(JOKER_STR.to_string());
+    }
+    PlayRow { kind: kind.to_string(), cards }
+}
+
+fn num_jokers(play: &Play) -> usize {
+    play.wilds.iter().filter(|w| w.wild.is_none()).count()
+}
+
+fn write_game<W: Write>(out: &mut W, format: ExportFormat, game: &GameRow) -> io::Result<()> {
+    match format {
+        ExportFormat::Csv => {
+            let went_out = game.went_out.map_or(String::new(), |seat| seat.to_string());
+            write!(
+                out,
+                "{},{},{},{},{},{},{}",
+                game.match_index, game.round, game.seed, game.dealer, game.turns, game.outcome, went_out,
+            )?;
+            for (player, score) in game.players.iter().zip(game.scores.iter()) {
+                write!(out, ",{},{}", csv_field(player), score)?;
+            }
+            writeln!(out)
+        },
+        #[cfg(feature = "json")]
+        ExportFormat::JsonLines => write_json_line(out, game),
+    }
+}
+
+fn write_turn<W: Write>(out: &mut W, format: ExportFormat, turn: &TurnRow) -> io::Result<()> {
+    match format {
+        ExportFormat::Csv => {
+            let plays: Vec<String> = turn
+                .plays
+                .iter()
+                .map(|play| format!("{}[{}]", play.kind, play.cards.join(" ")))
+                .collect();
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{},{},{}",
+                turn.match_index,
+                turn.round,
+                turn.turn,
+                turn.player,
+                turn.hand_size,
+                turn.draw,
+                turn.dig_depth,
+                plays.join(";"),
+                turn.discard.as_deref().unwrap_or(""),
+            )
+        },
+        #[cfg(feature = "json")]
+        ExportFormat::JsonLines => write_json_line(out, turn),
+    }
+}
+
+#[cfg(feature = "json")]
+fn write_json_line<W: Write, T: serde::Serialize>(out: &mut W, row: &T) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, row)?;
+    writeln!(out)
+}
+
+pub fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::{Card, CardMultiset, CardSet};
+    use crate::game::Draw;
+    use crate::rules::RuleSet;
+
+    fn cards(s: &str) -> Vec<Card> {
+        s.split_whitespace().map(|c| c.parse().unwrap()).collect()
+    }
+
+    // Seat 1 draws from the stock and throws the two of spades. Seat 2 digs
+    // two deep for the seven of hearts and melds out without discarding
+    fn finished_round() -> GameState {
+        let mut state = GameState::deal(3, 0, &RuleSet::default(), 21).unwrap();
+        state.hands[1] = CardMultiset::from_vec(&cards("A:S 3:S 8:D J:H Q:S 4:C 2:S"));
+        state.hands[2] = CardMultiset::from_vec(&cards("5:H 6:H 9:C 9:D 9:S 2:D 2:C"));
+        state.discard_pile = cards("7:H");
+
+        state.apply_draw(&Draw::Stock);
+        state.apply_discard(&"2:S".parse().unwrap());
+
+        let run = CardSet::from_vec(&cards("5:H 6:H 7:H"));
+        let dig = state
+            .legal_draws()
+            .into_iter()
+            .find(|draw| matches!(draw, Draw::Dig(play) if play.cards_used == run))
+            .unwrap();
+        state.apply_draw(&dig);
+        for meld in ["9:C 9:D 9:S", "2:S 2:D 2:C"] {
+            let meld = CardSet::from_vec(&cards(meld));
+            let play = state.legal_plays().into_iter().find(|play| play.cards_used == meld).unwrap();
+            state.apply_play(&play);
+        }
+        assert_eq!(state.outcome(), Some(RoundOutcome::WentOut(2)));
+        let scores = state.scores();
+        state.history.push(GameEvent::RoundOver { outcome: RoundOutcome::WentOut(2), scores });
+        state
+    }
+
+    fn players() -> Vec<String> {
+        vec!["greedy".to_string(), "heuristic".to_string(), "my, bot".to_string()]
+    }
+
+    fn export(format: ExportFormat, state: &GameState) -> (String, String) {
+        let mut exporter = Exporter::new(format, players(), vec![]).with_turns(vec![]);
+        exporter.write_round(4, 1, state).unwrap();
+        let Exporter { games, turns, .. } = exporter;
+        (String::from_utf8(games).unwrap(), String::from_utf8(turns.unwrap()).unwrap())
+    }
+
+    #[test]
+    fn csv_game_rows_have_the_seed_players_scores_and_turns() {
+        let state = finished_round();
+        let (games, _) = export(ExportFormat::Csv, &state);
+        let lines: Vec<&str> = games.lines().collect();
+        assert_eq!(
+            lines[0],
+            "match_index,round,seed,dealer,turns,outcome,went_out,player_0,score_0,player_1,score_1,player_2,score_2",
+        );
+        let scores = state.scores();
+        let expected = format!(
+            "4,1,21,0,{},WentOut,2,greedy,{},heuristic,{},\"my, bot\",{}",
+            state.turns, scores[0], scores[1], scores[2],
+        );
+        assert_eq!(lines[1], expected);
+        assert_eq!(lines.len(), 2);
+    }
+
+    #[test]
+    fn csv_turn_rows_have_the_draw_dig_plays_and_discard() {
+        let (_, turns) = export(ExportFormat::Csv, &finished_round());
+        let lines: Vec<&str> = turns.lines().collect();
+        assert_eq!(lines[0], "match_index,round,turn,player,hand_size,draw,dig_depth,plays,discard");
+        assert_eq!(lines[1], "4,1,0,1,7,stock,0,,2:S");
+        assert_eq!(
+            lines[2],
+            "4,1,1,2,7,discard,2,StraightFlush[5:H 6:H 7:H];Multiple[9:S 9:C 9:D];Multiple[2:S 2:C 2:D],",
+        );
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_lines_have_the_same_fields() {
+        let (games, turns) = export(ExportFormat::JsonLines, &finished_round());
+        let games: Vec<serde_json::Value> = games.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0]["seed"], 21);
+        assert_eq!(games[0]["players"][2], "my, bot");
+        assert_eq!(games[0]["scores"].as_array().unwrap().len(), 3);
+        assert!(games[0]["turns"].is_u64());
+
+        let turns: Vec<serde_json::Value> = turns.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(turns.len(), 2);
+        assert_eq!(turns[0]["hand_size"], 7);
+        assert_eq!(turns[0]["draw"], "stock");
+        assert_eq!(turns[0]["discard"], "2:S");
+        assert_eq!(turns[1]["dig_depth"], 2);
+        assert_eq!(turns[1]["plays"].as_array().unwrap().len(), 3);
+        assert_eq!(turns[1]["plays"][0]["cards"][2], "7:H");
+        assert!(turns[1]["discard"].is_null());
+    }
+}
@@ -0,0 +1,182 @@
+use crate::card::{self, Card, CardMultiset, CardSet};
+use crate::game::{Action, Draw, PlayerView};
+use crate::score::WildUse;
+
+// Turning what one player can see (their PlayerView, plus their opponent
+// model) into a fixed-length list of numbers, for training models outside of
+// rust.
+//
+// Cards are 52 slots each, in CardSet bit order (suit * 13 + value, ace low).
+// Opponents go in turn order after the observer. There's room for
+// MAX_OPPONENTS of them: the slots for missing ones are all 0, extra ones get
+// left out.
+//
+// The layout, in order:
+//     hand                  52   copies held
+//     discard pile          52   1 for the top card, 1/2 for the one under, ...
+//     runs on the table     52
+//     sets on the table     52
+//     each opponent         52   chance they hold each card
+//                            4   there at all, hand size / 13,
+//                                melded points / 100, match score / 500
+//     the observer           6   jokers held, hand size / 13,
+//                                melded points / 100, match score / 500,
+//                                stock size / 52, discard pile size / 52
+//
+// Actions get their own encoding (ACTION_LEN numbers) so a model can score
+// (features, action) pairs:
+//     kind                   6   one of stock, top discard, dig, play,
+//                                discard, knock
+//     cards                 52   the cards played, or the card drawn/discarded
+//     jokers                 1   jokers used
+//     dig depth              1   cards taken off the pile / 52
+
+pub const NUM_CARDS: usize = 52;
+pub const MAX_OPPONENTS: usize = 3;
+const OPPONENT_LEN: usize = NUM_CARDS + 4;
+const OBSERVER_LEN: usize = 6;
+pub const FEATURE_LEN: usize = 4 * NUM_CARDS + MAX_OPPONENTS * OPPONENT_LEN + OBSERVER_LEN;
+const NUM_ACTION_KINDS: usize = 6;
+pub const ACTION_LEN: usize = NUM_ACTION_KINDS + NUM_CARDS + 2;
+
+// match_scores can be empty when there's no match going on
+pub fn encode(view: &PlayerView, match_scores: &[i32]) -> Vec<f32> {
+    let mut features = Vec::with_capacity(FEATURE_LEN);
+    let model = view.opponent_model();
+    let observer = view.player;
+    let hand = view.hand();
+    let melded_points = view.melded_points();
+    let match_score = |player: usize| match_scores.get(player).map_or(0.0, |s| *s as f32 / 500.0);
+
+    push_hand(&mut features, hand);
+
+    let mut discards = [0.0f32; NUM_CARDS];
+    // With more than one deck a card can be in there twice, and the copy
+    // nearest the top is the one that counts
+    for (depth, card) in view.discard_pile().iter().rev().enumerate() {
+        let slot = &mut discards[card::bit_of(card)];
+        *slot = slot.max(1.0 / (depth + 1) as f32);
+    }
+    features.extend_from_slice(&discards);
+
+    push_cards(&mut features, &view.played_cards().straight_flush_played);
+    push_cards(&mut features, &view.played_cards().multiple_played);
+
+    let num_players = view.num_players();
+    for i in 1..=MAX_OPPONENTS {
+        if i >= num_players {
+            features.extend(std::iter::repeat_n(0.0, OPPONENT_LEN));
+            continue;
+        }
+        let player = (observer + i) % num_players;
+        let mut probabilities = [0.0; NUM_CARDS];
+        for (card, probability) in model.card_probabilities(player, hand) {
+            probabilities[card::bit_of(&card)] = probability as f32;
+        }
+        features.extend_from_slice(&probabilities);
+        features.push(1.0);
+        features.push(model.players[player].hand_size as f32 / 13.0);
+        features.push(melded_points[player] as f32 / 100.0);
+        features.push(match_score(player));
+    }
+
+    features.push(hand.num_jokers() as f32);
+    features.push(hand.len() as f32 / 13.0);
+    features.push(melded_points[observer] as f32 / 100.0);
+    features.push(match_score(observer));
+    features.push(view.stock_size() as f32 / NUM_CARDS as f32);
+    features.push(view.discard_pile().len() as f32 / NUM_CARDS as f32);
+
+    features
+}
+
+// `view` is from just before the action, for the top discard
+pub fn encode_action(action: &Action, view: &PlayerView) -> Vec<f32> {
+    let mut encoded = vec![0.0; ACTION_LEN];
+    let (kind, jokers, depth) = match action {
+        Action::Draw(Draw::Stock) => (0, 0, 0),
+        Action::Draw(Draw::TopDiscard) => {
+            if let Some(top) = view.discard_pile().last() {
+                set_card(&mut encoded, top);
+            }
+            (1, 0, 1)
+        },
+        Action::Draw(Draw::Dig(play)) => {
+            for card in play.cards_used.as_ordered_list().iter() {
+                set_card(&mut encoded, card);
+            }
+            (2, num_jokers(&play.wilds), play.cards_acquired.len())
+        },
+        Action::Play(play) => {
+            for card in play.cards_used.as_ordered_list().iter() {
+                set_card(&mut encoded, card);
+            }
+            (3, num_jokers(&play.wilds), 0)
+        },
+        Action::Discard(card) => {
+            set_card(&mut encoded, card);
+            (4, 0, 0)
+        },
+        Action::DiscardJoker => (4, 1, 0),
+        Action::Knock(card) => {
+            set_card(&mut encoded, card);
+            (5, 0, 0)
+        },
+    };
+    encoded[kind] = 1.0;
+    encoded[NUM_ACTION_KINDS + NUM_CARDS] = jokers as f32;
+    encoded[NUM_ACTION_KINDS + NUM_CARDS + 1] = depth as f32 / NUM_CARDS as f32;
+    encoded
+}
+
+fn set_card(encoded: &mut [f32], card: &Card) {
+    encoded[NUM_ACTION_KINDS + card::bit_of(card)] = 1.0;
+}
+
+fn push_cards(features: &mut Vec<f32>, cards: &CardSet) {
+    let bits = cards.bits();
+    features.extend((0..NUM_CARDS).map(|bit| ((bits >> bit) & 1) as f32));
+}
+
+// Copy counts, since there can be more than one deck
+fn push_hand(features: &mut Vec<f32>, hand: &CardMultiset) {
+    features.extend((0..NUM_CARDS).map(|bit| hand.count(&card::card_of_bit(bit)) as f32));
+}
+
+fn num_jokers(wilds: &[WildUse]) -> usize {
+    wilds.iter().filter(|w| w.wild.is_none()).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameState;
+    use crate::rules::RuleSet;
+
+    fn discard_slot(features: &[f32], card: &Card) -> f32 {
+        features[NUM_CARDS + card::bit_of(card)]
+    }
+
+    #[test]
+    fn features_and_actions_are_the_advertised_length() {
+        let state = GameState::deal(3, 0, &RuleSet::default(), 2).unwrap();
+        let view = state.view(state.current_player);
+        assert_eq!(encode(&view, &[]).len(), FEATURE_LEN);
+        for action in view.legal_actions().iter() {
+            assert_eq!(encode_action(action, &view).len(), ACTION_LEN);
+        }
+    }
+
+    #[test]
+    fn a_buried_copy_does_not_hide_the_top_one() {
+        let rules = RuleSet { num_decks: Some(2), ..RuleSet::default() };
+        let mut state = GameState::deal(2, 0, &rules, 2).unwrap();
+        let top: Card = "9:S".parse().unwrap();
+        let other: Card = "3:D".parse().unwrap();
+        state.discard_pile = vec![top, other, top];
+
+        let features = encode(&state.view(0), &[]);
+        assert_eq!(discard_slot(&features, &top), 1.0);
+        assert_eq!(discard_slot(&features, &other), 0.5);
+    }
+}
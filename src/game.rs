@@ -0,0 +1,954 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::card::{Card, CardMultiset, NUM_POSSIBLE_CARDS};
+use crate::deck::{Deck, DeckCard};
+use crate::gin;
+use crate::opponent::OpponentModel;
+use crate::rules::{GameKind, RuleSet, StockExhaustion};
+use crate::score::{self, Play, PlayedCards};
+
+// A round of 500 Rum, start to finish.
+//
+// Bigger tables shuffle more decks together (see RuleSet::decks_for), so a
+// hand can hold two of the same card and hands are multisets.
+// A turn is: draw (from the stock, the top discard, or dig deeper and play the
+// card you dug for right away), lay down as many plays as you like, then
+// discard. The round ends as soon as someone has no cards left, or when
+// there's nothing left to draw (see rules.stock_exhaustion for what happens
+// when the stock runs out). Everyone scores what they melded minus what's left
+// in hand.
+//
+// With rules.game set to Gin (gin::rules()) the same loop plays a round of Gin
+// Rummy instead: two players, ten cards each, no digging and no plays. A turn
+// ends with a discard, or with a knock if the deadwood left is low enough,
+// which ends the round and goes to the showdown (gin::round_scores). If the
+// stock gets down to its last two cards first, nobody scores.
+//
+// Players can keep passing the top discard back and forth forever, so rounds
+// also stop at a turn cap. That gets reported separately since nobody really
+// finished the round.
+//
+// Strategies get asked for each decision and are told about everything that
+// happens at the table (the GameEvents) so they can keep track of what other
+// players are up to. They're handed a PlayerView rather than the state itself,
+// which only shows what that player could see sitting at the table.
+
+pub const DEFAULT_TURN_CAP: usize = 500;
+
+// 13 cards each heads up, 7 with more players. Always 10 for Gin
+pub fn hand_size(num_players: usize, rules: &RuleSet) -> usize {
+    match rules.game {
+        GameKind::Gin => gin::HAND_SIZE,
+        GameKind::FiveHundred if num_players == 2 => 13,
+        GameKind::FiveHundred => 7,
+    }
+}
+
+// Whether a round can be dealt to this many players: the decks need to cover
+// every hand plus the upcard, and Gin is heads up only
+pub fn check_players(num_players: usize, rules: &RuleSet) -> Result<(), String> {
+    if num_players < 2 {
+        return Err(format!("Need at least 2 players, got {}", num_players));
+    }
+    if rules.game == GameKind::Gin && num_players != 2 {
+        return Err(format!("Gin is for 2 players, not {}", num_players));
+    }
+    if rules.game == GameKind::Gin && (rules.decks_for(num_players) != 1 || rules.jokers_per_deck != 0) {
+        return Err("Gin is played with one deck and no jokers".to_string());
+    }
+    let num_decks = rules.decks_for(num_players);
+    let num_cards = num_decks * (NUM_POSSIBLE_CARDS + rules.jokers_per_deck);
+    let needed = num_players * hand_size(num_players, rules) + 1;
+    if num_cards < needed {
+        return Err(format!(
+            "{} players need {} cards but {} deck(s) only have {}",
+            num_players, needed, num_decks, num_cards,
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+pub enum Draw {
+    Stock,
+    // Just the top card, it doesn't have to be played
+    TopDiscard,
+    // Take everything down to the deepest card the play needs and play it now
+    Dig(Box<Play>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoundOutcome {
+    WentOut(usize),
+    // Gin only
+    Knocked(usize),
+    // Someone needed to draw and there wasn't anything to draw
+    StockExhausted,
+    TurnCap,
+}
+
+// Any single step of a turn, for bots that search over the whole game rather
+// than answering one question at a time
+#[derive(Clone)]
+pub enum Action {
+    Draw(Draw),
+    Play(Box<Play>),
+    // Ends the turn
+    Discard(Card),
+    // Ends the turn with a hand of nothing but jokers. The discard pile only
+    // holds real cards, so it goes under the stock like a joker upcard
+    DiscardJoker,
+    // Gin only. Discards the card and ends the round
+    Knock(Card),
+}
+
+// Things everyone at the table sees happen
+#[derive(Clone)]
+pub enum GameEvent {
+    RoundStarted {
+        dealer: usize,
+        hand_sizes: Vec<usize>,
+        upcard: Card,
+        // What got shuffled together
+        num_decks: usize,
+        jokers_per_deck: usize,
+    },
+    DrewFromStock {
+        player: usize,
+    },
+    // In pile order, so the card that was on top is last
+    TookDiscards {
+        player: usize,
+        cards: Vec<Card>,
+    },
+    Played {
+        player: usize,
+        play: Box<Play>,
+    },
+    Discarded {
+        player: usize,
+        card: Card,
+    },
+    // Went under the stock, see Action::DiscardJoker
+    DiscardedJoker {
+        player: usize,
+    },
+    // Gin only. The discard that came with the knock
+    Knocked {
+        player: usize,
+        card: Card,
+    },
+    // All but the top discard went back into the stock
+    StockReshuffled {
+        num_cards: usize,
+    },
+    RoundOver {
+        outcome: RoundOutcome,
+        scores: Vec<i32>,
+    },
+}
+
+// Strategies only ever get to see their own PlayerView, never the GameState
+pub trait Strategy {
+    fn choose_draw(&mut self, view: &PlayerView) -> Draw;
+
+    // Gets asked again after every play until it says None
+    fn choose_play(&mut self, view: &PlayerView) -> Option<Play>;
+
+    fn choose_discard(&mut self, view: &PlayerView) -> Card;
+
+    // Gin only, and only asked when discarding `card` leaves few enough
+    // points to knock. Knocking as soon as you can is the usual way to start
+    fn choose_knock(&mut self, _view: &PlayerView, _card: &Card) -> bool {
+        true
+    }
+
+    fn observe(&mut self, _event: &GameEvent) {}
+}
+
+#[derive(Clone)]
+pub struct GameState {
+    pub rules: RuleSet,
+    pub hands: Vec<CardMultiset>,
+    pub stock: Deck,
+    pub discard_pile: Vec<Card>,
+    pub played_cards: PlayedCards,
+    // Points laid down this round, per player
+    pub melded_points: Vec<u32>,
+    pub dealer: usize,
+    pub current_player: usize,
+    // Whether the current player has drawn yet this turn
+    pub has_drawn: bool,
+    pub turns: usize,
+    // Gin only, whoever knocked
+    pub knocked: Option<usize>,
+    // The round stops here no matter what
+    pub turn_cap: usize,
+    // For reshuffling the discard pile into a new stock
+    pub seed: u64,
+    pub history: Vec<GameEvent>,
+}
+
+impl GameState {
+    // Deals from a seeded stock of as many decks as the table needs and turns
+    // up the first discard. The player after the dealer goes first. Errors if
+    // check_players does
+    pub fn deal(num_players: usize, dealer: usize, rules: &RuleSet, seed: u64) -> Result<Self, String> {
+        check_players(num_players, rules)?;
+        let num_decks = rules.decks_for(num_players);
+        let mut stock = Deck::shuffled(num_decks, rules.jokers_per_deck, seed);
+        let hands = stock.deal(num_players, hand_size(num_players, rules));
+        // A joker can't start the discard pile, so it goes to the bottom of the
+        // stock and the next card gets turned up instead
+        let upcard = loop {
+            match stock.draw() {
+                Some(DeckCard::Card(card)) => break card,
+                Some(DeckCard::Joker) => stock.put_under(DeckCard::Joker),
+                None => panic!("Invalid game state: no card left to turn up after dealing"),
+            }
+        };
+
+        let hand_sizes = hands.iter().map(|hand| hand.len()).collect();
+        let started = GameEvent::RoundStarted {
+            dealer,
+            hand_sizes,
+            upcard,
+            num_decks,
+            jokers_per_deck: rules.jokers_per_deck,
+        };
+        Ok(GameState {
+            rules: rules.clone(),
+            hands,
+            stock,
+            discard_pile: vec![upcard],
+            played_cards: PlayedCards::default(),
+            melded_points: vec![0; num_players],
+            dealer,
+            current_player: (dealer + 1) % num_players,
+            has_drawn: false,
+            turns: 0,
+            knocked: None,
+            turn_cap: DEFAULT_TURN_CAP,
+            seed,
+            history: vec![started],
+        })
+    }
+
+    pub fn num_players(&self) -> usize {
+        self.hands.len()
+    }
+
+    pub fn view(&self, player: usize) -> PlayerView<'_> {
+        PlayerView { state: self, player }
+    }
+
+    pub fn current_hand(&self) -> &CardMultiset {
+        &self.hands[self.current_player]
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.outcome().is_some()
+    }
+
+    // None while the round's still going
+    pub fn outcome(&self) -> Option<RoundOutcome> {
+        if let Some(player) = self.hands.iter().position(|hand| hand.is_empty()) {
+            return Some(RoundOutcome::WentOut(player));
+        }
+        if let Some(player) = self.knocked {
+            return Some(RoundOutcome::Knocked(player));
+        }
+        if self.turns >= self.turn_cap {
+            return Some(RoundOutcome::TurnCap);
+        }
+        if !self.has_drawn && self.is_gin() && self.stock.len() <= gin::DEAD_STOCK_SIZE {
+            return Some(RoundOutcome::StockExhausted);
+        }
+        if !self.has_drawn && !self.can_draw() {
+            return Some(RoundOutcome::StockExhausted);
+        }
+        None
+    }
+
+    fn can_draw(&self) -> bool {
+        if !self.stock.is_empty() {
+            return true;
+        }
+        match self.rules.stock_exhaustion {
+            StockExhaustion::EndRound => false,
+            StockExhaustion::ReshuffleDiscards => self.discard_pile.len() > 1,
+            StockExhaustion::DiscardPileOnly => !self.discard_pile.is_empty(),
+        }
+    }
+
+    // Whether drawing from the stock is an option (reshuffling first if need be)
+    fn can_draw_from_stock(&self) -> bool {
+        !self.stock.is_empty()
+            || (self.rules.stock_exhaustion == StockExhaustion::ReshuffleDiscards
+                && self.discard_pile.len() > 1)
+    }
+
+    pub fn is_gin(&self) -> bool {
+        self.rules.game == GameKind::Gin
+    }
+
+    // Melded points minus what's still in hand. For Gin it's the showdown if
+    // somebody knocked, and nothing for anyone otherwise
+    pub fn scores(&self) -> Vec<i32> {
+        if self.is_gin() {
+            let hands: Vec<_> = self.hands.iter().map(|hand| hand.distinct()).collect();
+            return match self.knocked {
+                Some(knocker) => gin::round_scores(&hands, knocker),
+                None => vec![0; self.num_players()],
+            };
+        }
+        self.hand_penalties()
+            .into_iter()
+            .zip(self.melded_points.iter())
+            .map(|(penalty, melded)| *melded as i32 - penalty as i32)
+            .collect()
+    }
+
+    // What everyone's still holding is worth against them. Deadwood for Gin
+    pub fn hand_penalties(&self) -> Vec<u32> {
+        self.hands
+            .iter()
+            .map(|hand| match self.rules.game {
+                GameKind::FiveHundred => score::hand_points(hand, &self.rules),
+                GameKind::Gin => gin::Arrangement::best(&hand.distinct()).deadwood_points(),
+            })
+            .collect()
+    }
+
+    // Gin only. Whether the current player could knock discarding `card`
+    pub fn can_knock_with(&self, card: &Card) -> bool {
+        let hand = self.current_hand();
+        if !self.is_gin() || !self.has_drawn || self.is_over() || !hand.contains(card) {
+            return false;
+        }
+        let mut rest = hand.distinct();
+        rest.remove(card);
+        gin::Arrangement::best(&rest).can_knock()
+    }
+
+    pub fn legal_draws(&self) -> Vec<Draw> {
+        let mut draws = vec![];
+        if self.can_draw_from_stock() {
+            draws.push(Draw::Stock);
+        }
+        if !self.discard_pile.is_empty() {
+            draws.push(Draw::TopDiscard);
+        }
+        if self.is_gin() {
+            return draws;
+        }
+        for play in score::all_possible_plays_multi(
+            self.current_hand(),
+            &self.discard_pile,
+            &self.played_cards,
+            &self.rules,
+        ) {
+            if play.cards_acquired.is_empty() {
+                continue;
+            }
+            let cards_left = self.current_hand().len() + play.cards_acquired.len()
+                - play.cards_used.as_ordered_list().len()
+                - num_jokers_used(&play);
+            if self.leaves_a_discard(cards_left) {
+                draws.push(Draw::Dig(Box::new(play)));
+            }
+        }
+        draws
+    }
+
+    // Plays out of the current hand, after drawing
+    pub fn legal_plays(&self) -> Vec<Play> {
+        if self.is_gin() {
+            return vec![];
+        }
+        let hand = self.current_hand();
+        score::all_possible_plays_multi(hand, &[], &self.played_cards, &self.rules)
+            .into_iter()
+            .filter(|play| {
+                let cards_left = hand.len()
+                    - play.cards_used.as_ordered_list().len()
+                    - num_jokers_used(play);
+                self.leaves_a_discard(cards_left)
+            })
+            .collect()
+    }
+
+    // Everything the current player could do next. Before drawing that's the
+    // draws, after it's any play or ending the turn with a discard
+    pub fn legal_actions(&self) -> Vec<Action> {
+        if self.is_over() {
+            return vec![];
+        }
+        if !self.has_drawn {
+            return self.legal_draws().into_iter().map(Action::Draw).collect();
+        }
+
+        let mut actions: Vec<Action> = self
+            .legal_plays()
+            .into_iter()
+            .map(|play| Action::Play(Box::new(play)))
+            .collect();
+        // Copies of the same card are the same discard
+        let hand = self.current_hand().distinct();
+        for card in hand.as_ordered_list() {
+            actions.push(Action::Discard(card));
+        }
+        if self.must_discard_joker() {
+            actions.push(Action::DiscardJoker);
+        }
+        if self.is_gin() {
+            for (card, deadwood) in gin::deadwood_after_discards(&hand) {
+                if deadwood <= gin::MAX_KNOCK_DEADWOOD {
+                    actions.push(Action::Knock(card));
+                }
+            }
+        }
+        actions
+    }
+
+    pub fn apply_action(&mut self, action: &Action) {
+        match action {
+            Action::Draw(draw) => self.apply_draw(draw),
+            Action::Play(play) => self.apply_play(play),
+            Action::Discard(card) => self.apply_discard(card),
+            Action::DiscardJoker => self.apply_discard_joker(),
+            Action::Knock(card) => self.apply_knock(card),
+        }
+    }
+
+    // Panics if the draw isn't legal
+    pub fn apply_draw(&mut self, draw: &Draw) {
+        let player = self.current_player;
+        self.has_drawn = true;
+        match draw {
+            Draw::Stock => {
+                if self.stock.is_empty()
+                    && self.rules.stock_exhaustion == StockExhaustion::ReshuffleDiscards
+                {
+                    self.reshuffle_discards();
+                }
+                match self.stock.draw() {
+                    Some(DeckCard::Card(card)) => self.hands[player].add(&card),
+                    Some(DeckCard::Joker) => self.hands[player].add_joker(),
+                    None => panic!("Invalid game state: drawing from an empty stock"),
+                }
+                self.history.push(GameEvent::DrewFromStock { player });
+            },
+            Draw::TopDiscard => {
+                let card = self.discard_pile.pop()
+                    .expect("Invalid game state: taking from an empty discard pile");
+                self.hands[player].add(&card);
+                self.history.push(GameEvent::TookDiscards { player, cards: vec![card] });
+            },
+            Draw::Dig(play) => {
+                let is_legal = self.legal_draws().iter().any(|legal| match legal {
+                    Draw::Dig(legal_play) => same_play(legal_play, play),
+                    _ => false,
+                });
+                if !is_legal {
+                    panic!("Invalid game state: can't dig for {}", play.cards_used);
+                }
+
+                let depth = play.cards_acquired.len();
+                let cards = self.discard_pile.split_off(self.discard_pile.len() - depth);
+                for card in cards.iter() {
+                    self.hands[player].add(card);
+                }
+                self.history.push(GameEvent::TookDiscards { player, cards });
+                self.lay_down(play);
+            },
+        }
+    }
+
+    // Panics if the play isn't legal
+    pub fn apply_play(&mut self, play: &Play) {
+        if !self.legal_plays().iter().any(|legal| same_play(legal, play)) {
+            panic!("Invalid game state: can't play {}", play.cards_used);
+        }
+        self.lay_down(play);
+    }
+
+    // Ends the turn. Panics if the card isn't in hand
+    pub fn apply_discard(&mut self, card: &Card) {
+        let player = self.current_player;
+        if !self.hands[player].contains(card) {
+            panic!("Invalid game state: discarding {} which isn't in hand", card);
+        }
+        self.hands[player].remove(card);
+        self.discard_pile.push(*card);
+        self.history.push(GameEvent::Discarded { player, card: *card });
+        self.end_turn();
+    }
+
+    // Ends the turn. Panics unless the hand is nothing but jokers
+    pub fn apply_discard_joker(&mut self) {
+        if !self.must_discard_joker() {
+            panic!("Invalid game state: discarding a joker with real cards in hand");
+        }
+        let player = self.current_player;
+        self.hands[player].remove_joker();
+        self.stock.put_under(DeckCard::Joker);
+        self.history.push(GameEvent::DiscardedJoker { player });
+        self.end_turn();
+    }
+
+    // Whether the current player has drawn and holds only jokers, so there's
+    // no real card to end the turn with
+    pub fn must_discard_joker(&self) -> bool {
+        let hand = self.current_hand();
+        self.has_drawn && hand.num_jokers() > 0 && hand.num_jokers() == hand.len()
+    }
+
+    // Discards and ends the round. Panics if the knock isn't legal
+    pub fn apply_knock(&mut self, card: &Card) {
+        if !self.can_knock_with(card) {
+            panic!("Invalid game state: can't knock discarding {}", card);
+        }
+        let player = self.current_player;
+        self.hands[player].remove(card);
+        self.discard_pile.push(*card);
+        self.knocked = Some(player);
+        self.history.push(GameEvent::Knocked { player, card: *card });
+        self.end_turn();
+    }
+
+    pub fn end_turn(&mut self) {
+        self.current_player = (self.current_player + 1) % self.num_players();
+        self.has_drawn = false;
+        self.turns += 1;
+    }
+
+    // Everything but the top discard becomes the new stock. Seeded off the
+    // round and turn so replays come out the same
+    fn reshuffle_discards(&mut self) {
+        let top = self.discard_pile.pop().expect("Invalid game state: reshuffling an empty discard pile");
+        let cards = std::mem::replace(&mut self.discard_pile, vec![top]);
+        let num_cards = cards.len();
+
+        let mut stock = Deck::from_cards(cards.into_iter().map(DeckCard::Card).collect());
+        let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(self.turns as u64));
+        stock.shuffle(&mut rng);
+        self.stock = stock;
+        self.history.push(GameEvent::StockReshuffled { num_cards });
+    }
+
+    fn lay_down(&mut self, play: &Play) {
+        let player = self.current_player;
+        for card in play.cards_used.as_ordered_list().iter() {
+            self.hands[player].remove(card);
+        }
+        for _ in 0..num_jokers_used(play) {
+            self.hands[player].remove_joker();
+        }
+        self.played_cards.add_play(play);
+        self.melded_points[player] += play.points(&self.rules);
+        self.history.push(GameEvent::Played { player, play: Box::new(play.clone()) });
+    }
+
+    // When you have to discard to go out, you can't meld your last card
+    fn leaves_a_discard(&self, cards_left: usize) -> bool {
+        !self.rules.final_discard_to_go_out || cards_left > 0
+    }
+}
+
+// One player's seat at the table: their own hand and everything public. The
+// state's kept private so there's no way to peek at other hands or the stock
+#[derive(Clone, Copy)]
+pub struct PlayerView<'a> {
+    state: &'a GameState,
+    pub player: usize,
+}
+
+impl<'a> PlayerView<'a> {
+    pub fn rules(&self) -> &'a RuleSet {
+        &self.state.rules
+    }
+
+    pub fn hand(&self) -> &'a CardMultiset {
+        &self.state.hands[self.player]
+    }
+
+    pub fn num_players(&self) -> usize {
+        self.state.num_players()
+    }
+
+    // Counting jokers
+    pub fn hand_sizes(&self) -> Vec<usize> {
+        self.state.hands.iter().map(|hand| hand.len()).collect()
+    }
+
+    // Bottom of the pile first
+    pub fn discard_pile(&self) -> &'a [Card] {
+        &self.state.discard_pile
+    }
+
+    pub fn played_cards(&self) -> &'a PlayedCards {
+        &self.state.played_cards
+    }
+
+    pub fn melded_points(&self) -> &'a [u32] {
+        &self.state.melded_points
+    }
+
+    pub fn stock_size(&self) -> usize {
+        self.state.stock.len()
+    }
+
+    pub fn dealer(&self) -> usize {
+        self.state.dealer
+    }
+
+    pub fn current_player(&self) -> usize {
+        self.state.current_player
+    }
+
+    pub fn has_drawn(&self) -> bool {
+        self.state.has_drawn
+    }
+
+    pub fn turns(&self) -> usize {
+        self.state.turns
+    }
+
+    pub fn turn_cap(&self) -> usize {
+        self.state.turn_cap
+    }
+
+    // Every event so far this round. They're all public
+    pub fn history(&self) -> &'a [GameEvent] {
+        &self.state.history
+    }
+
+    pub fn is_my_turn(&self) -> bool {
+        self.state.current_player == self.player
+    }
+
+    // Nothing's legal when it isn't this player's turn (and working it out
+    // would mean looking at someone else's hand)
+    pub fn legal_draws(&self) -> Vec<Draw> {
+        if !self.is_my_turn() {
+            return vec![];
+        }
+        self.state.legal_draws()
+    }
+
+    pub fn legal_plays(&self) -> Vec<Play> {
+        if !self.is_my_turn() {
+            return vec![];
+        }
+        self.state.legal_plays()
+    }
+
+    pub fn legal_actions(&self) -> Vec<Action> {
+        if !self.is_my_turn() {
+            return vec![];
+        }
+        self.state.legal_actions()
+    }
+
+    pub fn can_knock_with(&self, card: &Card) -> bool {
+        self.is_my_turn() && self.state.can_knock_with(card)
+    }
+
+    // What this player can work out about everyone's hands from what's
+    // happened so far in the round
+    pub fn opponent_model(&self) -> OpponentModel {
+        let mut model = OpponentModel::new(self.num_players(), self.player);
+        for event in self.state.history.iter() {
+            model.observe(event);
+        }
+        model
+    }
+
+    // A full state that looks the same from here, with the hidden parts
+    // supplied: everyone else's hands, the stock, and a seed for any
+    // reshuffles. For bots that search by guessing at what they can't see
+    pub fn fill_in(&self, other_hands: Vec<CardMultiset>, stock: Deck, seed: u64) -> GameState {
+        if other_hands.len() != self.num_players() {
+            panic!("Invalid game state: filling in {} hands for {} players", other_hands.len(), self.num_players());
+        }
+        let mut state = self.state.clone();
+        for (player, hand) in other_hands.into_iter().enumerate() {
+            if player != self.player {
+                state.hands[player] = hand;
+            }
+        }
+        state.stock = stock;
+        state.seed = seed;
+        state
+    }
+}
+
+// Plays a round to the end, telling every strategy about everything that
+// happens along the way. Returns the round's scores
+pub fn play_round(state: &mut GameState, strategies: &mut [Box<dyn Strategy>]) -> Vec<i32> {
+    let mut events_sent = 0;
+    broadcast(state, strategies, &mut events_sent);
+
+    while !state.is_over() {
+        let player = state.current_player;
+
+        let draw = strategies[player].choose_draw(&state.view(player));
+        state.apply_draw(&draw);
+        broadcast(state, strategies, &mut events_sent);
+
+        while !state.is_over() {
+            match strategies[player].choose_play(&state.view(player)) {
+                Some(play) => state.apply_play(&play),
+                None => break,
+            }
+            broadcast(state, strategies, &mut events_sent);
+        }
+        if state.is_over() {
+            break;
+        }
+
+        finish_turn(state, strategies[player].as_mut());
+        broadcast(state, strategies, &mut events_sent);
+    }
+
+    let scores = state.scores();
+    let outcome = state.outcome().expect("Invalid game state: round ended early");
+    state.history.push(GameEvent::RoundOver { outcome, scores: scores.clone() });
+    broadcast(state, strategies, &mut events_sent);
+    scores
+}
+
+// Ends the turn with the strategy's discard, as a knock if it's Gin and the
+// strategy wants to. A hand of nothing but jokers has no choice to make
+pub fn finish_turn<S: Strategy + ?Sized>(state: &mut GameState, strategy: &mut S) {
+    if state.must_discard_joker() {
+        state.apply_discard_joker();
+        return;
+    }
+    let player = state.current_player;
+    let card = strategy.choose_discard(&state.view(player));
+    if state.can_knock_with(&card) && strategy.choose_knock(&state.view(player), &card) {
+        state.apply_knock(&card);
+    } else {
+        state.apply_discard(&card);
+    }
+}
+
+fn broadcast(state: &GameState, strategies: &mut [Box<dyn Strategy>], events_sent: &mut usize) {
+    for event in state.history[*events_sent..].iter() {
+        for strategy in strategies.iter_mut() {
+            strategy.observe(event);
+        }
+    }
+    *events_sent = state.history.len();
+}
+
+fn num_jokers_used(play: &Play) -> usize {
+    play.wilds.iter().filter(|w| w.wild.is_none()).count()
+}
+
+// Whether two plays lay down the same thing the same way
+pub fn same_play(a: &Play, b: &Play) -> bool {
+    a.kind == b.kind
+        && a.cards_used.as_ordered_list() == b.cards_used.as_ordered_list()
+        && a.wilds.len() == b.wilds.len()
+        && a.wilds.iter().zip(b.wilds.iter())
+            .all(|(x, y)| x.wild == y.wild && x.stands_for == y.stands_for)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::CardSet;
+    use crate::greedy::GreedyStrategy;
+
+    fn cards(s: &str) -> Vec<Card> {
+        s.split_whitespace().map(|c| c.parse().unwrap()).collect()
+    }
+
+    // Two players, nothing on the table, `hand` for whoever's turn it is
+    fn with_hand(hand: &str, rules: &RuleSet) -> GameState {
+        let mut state = GameState::deal(2, 0, rules, 0).unwrap();
+        let player = state.current_player;
+        state.hands[player] = CardMultiset::from_vec(&cards(hand));
+        state
+    }
+
+    #[test]
+    fn big_tables_get_a_second_deck() {
+        let rules = RuleSet::default();
+        let state = GameState::deal(8, 0, &rules, 3).unwrap();
+        assert_eq!(rules.decks_for(8), 2);
+        assert!(state.hands.iter().all(|hand| hand.len() == 7));
+        assert_eq!(state.stock.len(), 104 - 8 * 7 - 1);
+        match &state.history[0] {
+            GameEvent::RoundStarted { num_decks, .. } => assert_eq!(*num_decks, 2),
+            _ => panic!("The round should start with RoundStarted"),
+        }
+    }
+
+    #[test]
+    fn two_decks_deal_some_duplicates() {
+        let rules = RuleSet { num_decks: Some(2), ..RuleSet::default() };
+        let has_duplicate = (0..20).any(|seed| {
+            let state = GameState::deal(8, 0, &rules, seed).unwrap();
+            state.hands.iter().any(|hand| hand.distinct().len() < hand.len())
+        });
+        assert!(has_duplicate);
+    }
+
+    #[test]
+    fn too_many_players_is_an_error() {
+        let rules = RuleSet { num_decks: Some(1), ..RuleSet::default() };
+        assert!(check_players(7, &rules).is_ok());
+        assert!(GameState::deal(8, 0, &rules, 0).is_err());
+        assert!(GameState::deal(1, 0, &RuleSet::default(), 0).is_err());
+        assert!(GameState::deal(3, 0, &gin::rules(), 0).is_err());
+    }
+
+    #[test]
+    fn multi_deck_rounds_with_jokers_finish() {
+        let rules = RuleSet { jokers_per_deck: 2, ..RuleSet::default() };
+        for seed in 0..10 {
+            let mut state = GameState::deal(6, 0, &rules, seed).unwrap();
+            let mut strategies: Vec<Box<dyn Strategy>> = (0..6).map(|_| Box::new(GreedyStrategy) as Box<dyn Strategy>).collect();
+            play_round(&mut state, &mut strategies);
+            assert!(state.outcome().is_some());
+        }
+    }
+
+    #[test]
+    fn digging_takes_every_card_above_the_one_used() {
+        let mut state = with_hand("5:H 6:H 2:C 9:D", &RuleSet::default());
+        state.discard_pile = cards("7:H K:S");
+        let player = state.current_player;
+
+        let dig = state.legal_draws().into_iter().find_map(|draw| match draw {
+            Draw::Dig(play) if play.cards_used == CardSet::from_vec(&cards("5:H 6:H 7:H")) => Some(play),
+            _ => None,
+        });
+        let dig = dig.expect("Digging for the seven of hearts should be legal");
+        assert_eq!(dig.cards_acquired.len(), 2);
+
+        state.apply_draw(&Draw::Dig(dig));
+        assert!(state.discard_pile.is_empty());
+        assert!(state.hands[player] == CardMultiset::from_vec(&cards("2:C 9:D K:S")));
+        assert!(state.has_drawn);
+    }
+
+    #[test]
+    fn cant_dig_when_the_rules_say_top_card_only() {
+        let rules = RuleSet { can_dig_discard_pile: false, ..RuleSet::default() };
+        let mut state = with_hand("5:H 6:H 2:C 9:D", &rules);
+        state.discard_pile = cards("7:H K:S");
+        assert!(state.legal_draws().iter().all(|draw| !matches!(draw, Draw::Dig(_))));
+    }
+
+    #[test]
+    fn melding_the_whole_hand_goes_out() {
+        let mut state = with_hand("5:H 6:H 7:H", &RuleSet::default());
+        state.has_drawn = true;
+        let player = state.current_player;
+        let play = state.legal_plays().into_iter().next().expect("The run should be playable");
+        state.apply_play(&play);
+        assert_eq!(state.outcome(), Some(RoundOutcome::WentOut(player)));
+    }
+
+    #[test]
+    fn going_out_can_require_a_final_discard() {
+        let rules = RuleSet { final_discard_to_go_out: true, ..RuleSet::default() };
+        let mut state = with_hand("5:H 6:H 7:H", &rules);
+        state.has_drawn = true;
+        assert!(state.legal_plays().is_empty());
+
+        let mut state = with_hand("5:H 6:H 7:H 2:C", &rules);
+        state.has_drawn = true;
+        let player = state.current_player;
+        let play = state.legal_plays().into_iter().next().expect("The run should be playable");
+        state.apply_play(&play);
+        state.apply_discard(&"2:C".parse().unwrap());
+        assert_eq!(state.outcome(), Some(RoundOutcome::WentOut(player)));
+    }
+
+    #[test]
+    fn a_hand_left_with_only_a_joker_can_discard_it() {
+        let rules = RuleSet { jokers_per_deck: 1, ..RuleSet::default() };
+        let mut state = with_hand("5:H 6:H 7:H", &rules);
+        let player = state.current_player;
+        state.hands[player].add_joker();
+        state.has_drawn = true;
+
+        let run = state
+            .legal_plays()
+            .into_iter()
+            .find(|play| play.wilds.is_empty())
+            .expect("The run should be playable");
+        state.apply_play(&run);
+        assert_eq!(state.current_hand().num_jokers(), 1);
+        assert_eq!(state.current_hand().len(), 1);
+
+        // Laying the joker off on the run would do too, but there's no real
+        // card to discard
+        let actions = state.legal_actions();
+        assert!(actions.iter().all(|action| !matches!(action, Action::Discard(_))));
+        assert!(actions.iter().any(|action| matches!(action, Action::DiscardJoker)));
+
+        let stock_size = state.stock.len();
+        state.apply_action(&Action::DiscardJoker);
+        assert_eq!(state.outcome(), Some(RoundOutcome::WentOut(player)));
+        assert_eq!(state.stock.len(), stock_size + 1);
+        assert!(matches!(state.stock.cards()[0], DeckCard::Joker));
+        assert!(matches!(state.history.last(), Some(GameEvent::DiscardedJoker { .. })));
+    }
+
+    #[test]
+    fn strategies_arent_asked_to_discard_from_only_jokers() {
+        let rules = RuleSet { jokers_per_deck: 2, ..RuleSet::default() };
+        let mut state = with_hand("", &rules);
+        let player = state.current_player;
+        state.hands[player].add_joker();
+        state.hands[player].add_joker();
+        state.has_drawn = true;
+        finish_turn(&mut state, &mut GreedyStrategy);
+        assert_eq!(state.hands[player].num_jokers(), 1);
+        assert!(!state.has_drawn);
+        assert!(state.current_player != player);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid game state")]
+    fn jokers_cant_be_discarded_while_holding_real_cards() {
+        let rules = RuleSet { jokers_per_deck: 1, ..RuleSet::default() };
+        let mut state = with_hand("2:C", &rules);
+        let player = state.current_player;
+        state.hands[player].add_joker();
+        state.has_drawn = true;
+        state.apply_discard_joker();
+    }
+
+    #[test]
+    fn empty_stock_reshuffles_all_but_the_top_discard() {
+        let rules = RuleSet { stock_exhaustion: StockExhaustion::ReshuffleDiscards, ..RuleSet::default() };
+        let mut state = with_hand("2:C 9:D", &rules);
+        state.stock = Deck::from_cards(vec![]);
+        state.discard_pile = cards("4:S 8:D J:C");
+        assert!(state.legal_draws().iter().any(|draw| matches!(draw, Draw::Stock)));
+
+        state.apply_draw(&Draw::Stock);
+        assert!(state.discard_pile == cards("J:C"));
+        assert_eq!(state.stock.len(), 1);
+        assert_eq!(state.current_hand().len(), 3);
+        assert!(state.history.iter().any(|event| matches!(event, GameEvent::StockReshuffled { num_cards: 2 })));
+    }
+
+    #[test]
+    fn empty_stock_ends_the_round_by_default() {
+        let mut state = with_hand("2:C 9:D", &RuleSet::default());
+        state.stock = Deck::from_cards(vec![]);
+        state.discard_pile = cards("4:S 8:D J:C");
+        assert!(state.legal_draws().iter().all(|draw| !matches!(draw, Draw::Stock)));
+        assert_eq!(state.outcome(), Some(RoundOutcome::StockExhausted));
+    }
+}
@@ -0,0 +1,234 @@
+use crate::game::{self, GameState, RoundOutcome, Strategy, DEFAULT_TURN_CAP};
+use crate::rules::RuleSet;
+
+// A whole game of 500 Rum: rounds until somebody gets to the target score
+// (rules.target_score). Scores carry over between rounds and can go negative,
+// and the deal moves one seat to the left each round.
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TieBreak {
+    // Nobody wins until one player is ahead on their own, so keep playing rounds
+    PlayOn,
+    // Everyone tied for the lead wins
+    Shared,
+    // Of the players tied for the lead, whoever did best in the last round.
+    // Shared if that's a tie too
+    LastRound,
+}
+
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct MatchConfig {
+    pub tie_break: TieBreak,
+    pub first_dealer: usize,
+    // Ends the match after this many rounds even if nobody got to the target,
+    // so a match between bots that never go out can't go on forever
+    pub max_rounds: usize,
+    // Same idea for each round
+    pub turn_cap: usize,
+}
+
+impl Default for MatchConfig {
+    fn default() -> Self {
+        MatchConfig {
+            tie_break: TieBreak::PlayOn,
+            first_dealer: 0,
+            max_rounds: 100,
+            turn_cap: DEFAULT_TURN_CAP,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RoundRecord {
+    pub dealer: usize,
+    pub seed: u64,
+    pub turns: usize,
+    pub outcome: RoundOutcome,
+    // Per player
+    pub melded_points: Vec<u32>,
+    pub hand_penalty: Vec<u32>,
+    pub scores: Vec<i32>,
+}
+
+pub struct Match {
+    pub rules: RuleSet,
+    pub config: MatchConfig,
+    // Running totals
+    pub scores: Vec<i32>,
+    pub rounds: Vec<RoundRecord>,
+    pub dealer: usize,
+    // Round n is dealt with seed + n
+    pub seed: u64,
+}
+
+impl Match {
+    // Errors if a round can't be dealt to this many players (see
+    // game::check_players)
+    pub fn new(num_players: usize, rules: &RuleSet, config: MatchConfig, seed: u64) -> Result<Self, String> {
+        game::check_players(num_players, rules)?;
+        Ok(Match {
+            rules: rules.clone(),
+            config,
+            scores: vec![0; num_players],
+            rounds: vec![],
+            dealer: config.first_dealer % num_players,
+            seed,
+        })
+    }
+
+    pub fn num_players(&self) -> usize {
+        self.scores.len()
+    }
+
+    // The next round's starting position
+    pub fn next_round(&self) -> GameState {
+        let seed = self.seed.wrapping_add(self.rounds.len() as u64);
+        let mut state = GameState::deal(self.num_players(), self.dealer, &self.rules, seed)
+            .expect("Invalid game state: the player count was checked when the match started");
+        state.turn_cap = self.config.turn_cap;
+        state
+    }
+
+    // Adds a finished round to the totals and passes the deal on
+    pub fn record_round(&mut self, state: &GameState) {
+        let outcome = state.outcome().expect("Invalid game state: recording an unfinished round");
+        let hand_penalty = state.hand_penalties();
+        let scores = state.scores();
+        for (total, score) in self.scores.iter_mut().zip(scores.iter()) {
+            *total += score;
+        }
+
+        self.rounds.push(RoundRecord {
+            dealer: self.dealer,
+            seed: self.seed.wrapping_add(self.rounds.len() as u64),
+            turns: state.turns,
+            outcome,
+            melded_points: state.melded_points.clone(),
+            hand_penalty,
+            scores,
+        });
+        self.dealer = (self.dealer + 1) % self.num_players();
+    }
+
+    pub fn play_round(&mut self, strategies: &mut [Box<dyn Strategy>]) -> &RoundRecord {
+        let mut state = self.next_round();
+        game::play_round(&mut state, strategies);
+        self.record_round(&state);
+        self.rounds.last().unwrap()
+    }
+
+    // Plays rounds until the match is decided and returns the winners
+    pub fn play(&mut self, strategies: &mut [Box<dyn Strategy>]) -> Vec<usize> {
+        while !self.is_over() {
+            self.play_round(strategies);
+        }
+        self.winners()
+    }
+
+    pub fn is_over(&self) -> bool {
+        if self.rounds.len() >= self.config.max_rounds {
+            return true;
+        }
+        let reached_target = self.scores.iter().any(|s| *s >= self.rules.target_score);
+        if !reached_target {
+            return false;
+        }
+        match self.config.tie_break {
+            TieBreak::PlayOn => self.leaders().len() == 1,
+            TieBreak::Shared | TieBreak::LastRound => true,
+        }
+    }
+
+    // Nobody until the match is over. More than one means a shared win
+    pub fn winners(&self) -> Vec<usize> {
+        if !self.is_over() {
+            return vec![];
+        }
+
+        let leaders = self.leaders();
+        if leaders.len() == 1 || self.config.tie_break != TieBreak::LastRound {
+            return leaders;
+        }
+
+        let last_round = match self.rounds.last() {
+            Some(round) => round,
+            None => return leaders,
+        };
+        let best = leaders.iter().map(|p| last_round.scores[*p]).max().unwrap();
+        leaders.into_iter().filter(|p| last_round.scores[*p] == best).collect()
+    }
+
+    // Everyone with the highest total
+    pub fn leaders(&self) -> Vec<usize> {
+        let best = *self.scores.iter().max().unwrap();
+        (0..self.num_players()).filter(|p| self.scores[*p] == best).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tied_at_the_target(tie_break: TieBreak) -> Match {
+        let config = MatchConfig { tie_break, ..MatchConfig::default() };
+        let mut game = Match::new(3, &RuleSet::default(), config, 0).unwrap();
+        game.scores = vec![500, 500, 120];
+        game
+    }
+
+    fn record(scores: Vec<i32>) -> RoundRecord {
+        RoundRecord {
+            dealer: 0,
+            seed: 0,
+            turns: 0,
+            outcome: RoundOutcome::TurnCap,
+            melded_points: vec![0; scores.len()],
+            hand_penalty: vec![0; scores.len()],
+            scores,
+        }
+    }
+
+    #[test]
+    fn play_on_keeps_going_through_a_tie() {
+        let mut game = tied_at_the_target(TieBreak::PlayOn);
+        assert!(!game.is_over());
+        assert!(game.winners().is_empty());
+
+        game.scores[1] = 510;
+        assert!(game.is_over());
+        assert_eq!(game.winners(), vec![1]);
+    }
+
+    #[test]
+    fn shared_tie_break_gives_everyone_tied_the_win() {
+        let game = tied_at_the_target(TieBreak::Shared);
+        assert!(game.is_over());
+        assert_eq!(game.winners(), vec![0, 1]);
+    }
+
+    #[test]
+    fn last_round_breaks_the_tie() {
+        let mut game = tied_at_the_target(TieBreak::LastRound);
+        game.rounds.push(record(vec![30, 50, 90]));
+        assert_eq!(game.winners(), vec![1]);
+
+        // Player 2 did best last round but isn't tied for the lead
+        game.rounds.push(record(vec![40, 40, 90]));
+        assert_eq!(game.winners(), vec![0, 1]);
+    }
+
+    #[test]
+    fn max_rounds_ends_the_match() {
+        let config = MatchConfig { max_rounds: 2, ..MatchConfig::default() };
+        let mut game = Match::new(2, &RuleSet::default(), config, 0).unwrap();
+        game.scores = vec![40, 75];
+        game.rounds.push(record(vec![0, 0]));
+        assert!(!game.is_over());
+        game.rounds.push(record(vec![40, 75]));
+        assert!(game.is_over());
+        assert_eq!(game.winners(), vec![1]);
+    }
+}
@@ -0,0 +1,354 @@
+use crate::card::{Card, CardSet, CardValue};
+use crate::meld;
+use crate::rules::{AceRule, GameKind, RuleSet};
+use crate::score::{self, PlayKind};
+
+// Gin Rummy
+//
+// Differences from 500 Rum that matter here:
+// - everyone gets 10 cards and nothing goes on the table during play
+// - you can end the round by knocking once your unmatched cards ("deadwood")
+//   add up to 10 or less, or going gin with no deadwood at all
+// - at the showdown the other player can lay off their deadwood onto the
+//   knocker's melds (not if it's gin though)
+// - whoever has less deadwood scores the difference, plus bonuses for gin or
+//   for undercutting the knocker
+
+pub const HAND_SIZE: usize = 10;
+pub const MAX_KNOCK_DEADWOOD: u32 = 10;
+pub const GIN_BONUS: u32 = 25;
+pub const UNDERCUT_BONUS: u32 = 25;
+pub const TARGET_SCORE: i32 = 100;
+// Nobody can draw the last two cards of the stock. If it gets down to that
+// without a knock the round's a wash
+pub const DEAD_STOCK_SIZE: usize = 2;
+
+// Aces are always low, only the top discard can be taken, and you discard
+// when you knock. GameState plays a round of it with these (and only with
+// two players)
+pub fn rules() -> RuleSet {
+    RuleSet {
+        game: GameKind::Gin,
+        ace_rule: AceRule::Low,
+        allow_wrap_around: false,
+        can_dig_discard_pile: false,
+        final_discard_to_go_out: true,
+        target_score: TARGET_SCORE,
+        ..RuleSet::default()
+    }
+}
+
+pub fn deadwood_value(card: &Card) -> u32 {
+    use CardValue::*;
+    match card.value {
+        Jack | Queen | King => 10,
+        value => value.index() as u32 + 1,
+    }
+}
+
+pub fn deadwood_points(cards: &CardSet) -> u32 {
+    cards.as_ordered_list().iter().map(deadwood_value).sum()
+}
+
+// Best deadwood left after throwing away each card in the hand
+pub fn deadwood_after_discards(hand: &CardSet) -> Vec<(Card, u32)> {
+    meld::least_deadwood_without_each(hand, &rules(), deadwood_value)
+}
+
+// A hand split up into melds and whatever's left over
+#[derive(Clone)]
+pub struct Arrangement {
+    pub melds: Vec<CardSet>,
+    pub deadwood: CardSet,
+}
+
+impl Arrangement {
+    pub fn new(hand: &CardSet, melds: Vec<CardSet>) -> Result<Self, String> {
+        let rules = rules();
+        let mut deadwood = *hand;
+        for meld in melds.iter() {
+            if score::meld_kind(meld, &rules).is_none() {
+                return Err(format!("Not a valid meld: {}", meld));
+            }
+            for card in meld.as_ordered_list().iter() {
+                if !deadwood.contains(card) {
+                    return Err(format!("Card {} isn't in the hand or is in two melds", card));
+                }
+                deadwood.remove(card);
+            }
+        }
+        Ok(Arrangement { melds, deadwood })
+    }
+
+    // The arrangement with the least deadwood
+    pub fn best(hand: &CardSet) -> Self {
+        let partition = meld::best_partition(hand, &rules(), deadwood_value);
+        Arrangement { melds: partition.melds, deadwood: partition.deadwood }
+    }
+
+    pub fn deadwood_points(&self) -> u32 {
+        deadwood_points(&self.deadwood)
+    }
+
+    pub fn can_knock(&self) -> bool {
+        self.deadwood_points() <= MAX_KNOCK_DEADWOOD
+    }
+
+    pub fn is_gin(&self) -> bool {
+        self.deadwood.as_ordered_list().is_empty()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShowdownOutcome {
+    Gin,
+    Knock,
+    Undercut,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Knocker,
+    Defender,
+}
+
+pub struct Showdown {
+    pub outcome: ShowdownOutcome,
+    pub winner: Side,
+    pub points: u32,
+    pub knocker_deadwood: u32,
+    // After laying off
+    pub defender_deadwood: u32,
+    pub layoffs: CardSet,
+}
+
+pub fn showdown(knocker: &Arrangement, defender: &Arrangement) -> Result<Showdown, String> {
+    let knocker_deadwood = knocker.deadwood_points();
+    if !knocker.can_knock() {
+        return Err(format!(
+            "Can't knock with {} deadwood (max is {})",
+            knocker_deadwood, MAX_KNOCK_DEADWOOD,
+        ));
+    }
+
+    if knocker.is_gin() {
+        let defender_deadwood = defender.deadwood_points();
+        return Ok(Showdown {
+            outcome: ShowdownOutcome::Gin,
+            winner: Side::Knocker,
+            points: defender_deadwood + GIN_BONUS,
+            knocker_deadwood,
+            defender_deadwood,
+            layoffs: CardSet::new(),
+        });
+    }
+
+    let layoffs = layoffs(&knocker.melds, &defender.deadwood);
+    let mut remaining = defender.deadwood;
+    for card in layoffs.as_ordered_list().iter() {
+        remaining.remove(card);
+    }
+    let defender_deadwood = deadwood_points(&remaining);
+
+    let showdown = if defender_deadwood <= knocker_deadwood {
+        Showdown {
+            outcome: ShowdownOutcome::Undercut,
+            winner: Side::Defender,
+            points: knocker_deadwood - defender_deadwood + UNDERCUT_BONUS,
+            knocker_deadwood,
+            defender_deadwood,
+            layoffs,
+        }
+    } else {
+        Showdown {
+            outcome: ShowdownOutcome::Knock,
+            winner: Side::Knocker,
+            points: defender_deadwood - knocker_deadwood,
+            knocker_deadwood,
+            defender_deadwood,
+            layoffs,
+        }
+    };
+    Ok(showdown)
+}
+
+// A round's scores after `knocker` knocked with `hands` (what was left after
+// the knocking discard). Both sides lay out their best arrangement, and
+// whoever wins the showdown gets the points
+pub fn round_scores(hands: &[CardSet], knocker: usize) -> Vec<i32> {
+    let defender = 1 - knocker;
+    let result = showdown(&Arrangement::best(&hands[knocker]), &Arrangement::best(&hands[defender]))
+        .expect("Invalid game state: knocked with too much deadwood");
+    let winner = match result.winner {
+        Side::Knocker => knocker,
+        Side::Defender => defender,
+    };
+    let mut scores = vec![0; hands.len()];
+    scores[winner] = result.points as i32;
+    scores
+}
+
+// Which of the defender's deadwood cards can go onto the knocker's melds.
+// Laying one off can make room for another (5 then 4 onto a 6-7-8), so keep
+// going until nothing else fits. Runs get first dibs since they can keep growing
+fn layoffs(knocker_melds: &[CardSet], deadwood: &CardSet) -> CardSet {
+    let rules = rules();
+    let mut melds: Vec<CardSet> = knocker_melds.to_vec();
+    melds.sort_by_key(|meld| match score::meld_kind(meld, &rules) {
+        Some(PlayKind::StraightFlush { .. }) => 0,
+        _ => 1,
+    });
+
+    let mut remaining = *deadwood;
+    let mut laid_off = CardSet::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for card in remaining.as_ordered_list().iter() {
+            for meld in melds.iter_mut() {
+                let mut extended = *meld;
+                extended.add(card);
+                if score::meld_kind(&extended, &rules).is_some() {
+                    *meld = extended;
+                    remaining.remove(card);
+                    laid_off.add(card);
+                    changed = true;
+                    break;
+                }
+            }
+        }
+    }
+    laid_off
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::CardMultiset;
+    use crate::game::{self, Action, GameEvent, GameState, RoundOutcome, Strategy};
+    use crate::greedy::GreedyStrategy;
+
+    fn cards(s: &str) -> CardSet {
+        let cards: Vec<Card> = s.split_whitespace().map(|c| c.parse().unwrap()).collect();
+        CardSet::from_vec(&cards)
+    }
+
+    // Player 0's turn, after drawing, holding `hand`
+    fn after_drawing(hand: &str) -> GameState {
+        let mut state = GameState::deal(2, 1, &rules(), 0).unwrap();
+        state.hands[0] = CardMultiset::from(&cards(hand));
+        state.has_drawn = true;
+        state
+    }
+
+    #[test]
+    fn greedy_players_finish_rounds() {
+        for seed in 0..20 {
+            let mut state = GameState::deal(2, 0, &rules(), seed).unwrap();
+            assert!(state.hands.iter().all(|hand| hand.len() == HAND_SIZE));
+            let mut strategies: Vec<Box<dyn Strategy>> = vec![Box::new(GreedyStrategy), Box::new(GreedyStrategy)];
+            let scores = game::play_round(&mut state, &mut strategies);
+
+            assert!(state.played_cards.straight_flush_played.is_empty());
+            assert!(state.played_cards.multiple_played.is_empty());
+            match state.outcome() {
+                Some(RoundOutcome::Knocked(knocker)) => {
+                    let hands: Vec<CardSet> = state.hands.iter().map(|hand| hand.distinct()).collect();
+                    assert!(Arrangement::best(&hands[knocker]).can_knock());
+                    assert_eq!(scores, round_scores(&hands, knocker));
+                    assert_eq!(scores.iter().filter(|score| **score > 0).count(), 1);
+                },
+                Some(RoundOutcome::StockExhausted) => {
+                    assert!(state.stock.len() <= DEAD_STOCK_SIZE);
+                    assert_eq!(scores, vec![0, 0]);
+                },
+                other => panic!("Round ended with {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn knocks_are_offered_when_the_deadwood_is_low_enough() {
+        let state = after_drawing("A:S 2:S 3:S 4:H 5:H 6:H 7:C 7:D 7:S 2:C K:D");
+        let knocks: Vec<String> = state
+            .legal_actions()
+            .iter()
+            .filter_map(|action| match action {
+                Action::Knock(card) => Some(card.to_string()),
+                _ => None,
+            })
+            .collect();
+        // 2 deadwood after throwing the king, 10 after the two of clubs
+        assert_eq!(knocks, vec!["2:C", "K:D"]);
+        assert!(state.legal_plays().is_empty());
+    }
+
+    #[test]
+    fn knocking_ends_the_round() {
+        let mut state = after_drawing("A:S 2:S 3:S 4:H 5:H 6:H 7:C 7:D 7:S 2:C K:D");
+        let king = "K:D".parse().unwrap();
+        state.apply_knock(&king);
+        assert_eq!(state.outcome(), Some(RoundOutcome::Knocked(0)));
+        assert!(state.discard_pile.last() == Some(&king));
+        assert!(matches!(state.history.last(), Some(GameEvent::Knocked { player: 0, .. })));
+        assert_eq!(state.hand_penalties()[0], 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid game state")]
+    fn knocking_with_too_much_deadwood_panics() {
+        let mut state = after_drawing("A:S 2:S 3:S 4:H 5:H 6:H 7:C 7:D 7:S 2:C K:D");
+        state.apply_knock(&"A:S".parse().unwrap());
+    }
+
+    #[test]
+    fn the_stock_goes_dead_with_two_cards_left() {
+        let mut state = GameState::deal(2, 0, &rules(), 0).unwrap();
+        while state.stock.len() > DEAD_STOCK_SIZE {
+            state.stock.draw();
+        }
+        assert_eq!(state.outcome(), Some(RoundOutcome::StockExhausted));
+        assert_eq!(state.scores(), vec![0, 0]);
+    }
+
+    #[test]
+    fn gin_scores_the_defenders_deadwood_plus_the_bonus() {
+        let knocker = Arrangement::best(&cards("A:S 2:S 3:S 4:S 4:H 5:H 6:H 7:C 7:D 7:S"));
+        let defender = Arrangement::best(&cards("8:S 8:H 8:D J:C Q:C K:C A:D 2:D 3:D 4:C"));
+        let result = showdown(&knocker, &defender).unwrap();
+        assert_eq!(result.outcome, ShowdownOutcome::Gin);
+        assert_eq!(result.winner, Side::Knocker);
+        assert_eq!(result.points, 4 + GIN_BONUS);
+        assert!(result.layoffs.is_empty());
+    }
+
+    #[test]
+    fn defender_with_no_more_deadwood_undercuts() {
+        let knocker = Arrangement::best(&cards("A:S 2:S 3:S 4:H 5:H 6:H 7:C 7:D 7:S 9:C"));
+        let defender = Arrangement::best(&cards("8:S 8:H 8:D J:C Q:C K:C A:D 2:D 3:D 4:C"));
+        let result = showdown(&knocker, &defender).unwrap();
+        assert_eq!(result.outcome, ShowdownOutcome::Undercut);
+        assert_eq!(result.winner, Side::Defender);
+        assert_eq!((result.knocker_deadwood, result.defender_deadwood), (9, 4));
+        assert_eq!(result.points, 9 - 4 + UNDERCUT_BONUS);
+    }
+
+    #[test]
+    fn defender_lays_off_onto_the_knockers_melds() {
+        let knocker = Arrangement::best(&cards("A:S 2:S 3:S 4:H 5:H 6:H 7:C 7:D 7:S 2:C"));
+        let defender = Arrangement::best(&cards("4:S 7:H K:D Q:C J:S 9:D 9:H 9:C 2:D 3:H"));
+        let result = showdown(&knocker, &defender).unwrap();
+        assert!(result.layoffs == cards("4:S 3:H 7:H"));
+        assert_eq!(result.outcome, ShowdownOutcome::Knock);
+        assert_eq!(result.winner, Side::Knocker);
+        assert_eq!(result.defender_deadwood, 32);
+        assert_eq!(result.points, 32 - 2);
+    }
+
+    #[test]
+    fn knocking_with_too_much_deadwood_is_an_error() {
+        let knocker = Arrangement::best(&cards("A:S 2:S 3:S 4:H 5:H 6:H 7:C 7:D 9:S K:C"));
+        let defender = Arrangement::best(&cards("8:S 8:H 8:D J:C Q:C K:D A:D 2:D 3:D 4:C"));
+        assert!(showdown(&knocker, &defender).is_err());
+    }
+}
@@ -0,0 +1,104 @@
+use std::cmp::Reverse;
+
+use crate::card::{Card, CardMultiset, CardSet};
+use crate::game::{Draw, PlayerView, Strategy};
+use crate::gin::{self, Arrangement};
+use crate::rules::{GameKind, RuleSet};
+use crate::score::{self, Play};
+
+// Simple and fast: dig when the play is worth more than the junk that comes
+// with it, lay down the biggest play there is, and throw away the most
+// expensive card that isn't close to making anything. Good enough as an
+// opponent to test against and quick enough to use for rollouts.
+//
+// In Gin it throws away whatever leaves the least deadwood, only takes the top
+// discard when that gets the deadwood down, and knocks as soon as it can.
+
+#[derive(Clone, Copy, Default)]
+pub struct GreedyStrategy;
+
+impl Strategy for GreedyStrategy {
+    fn choose_draw(&mut self, view: &PlayerView) -> Draw {
+        if view.rules().game == GameKind::Gin {
+            return gin_draw(view);
+        }
+        let draws = view.legal_draws();
+        let best_dig = draws
+            .iter()
+            .filter_map(|draw| match draw {
+                Draw::Dig(play) => Some((dig_value(play, view.rules()), play.clone())),
+                _ => None,
+            })
+            .max_by_key(|(value, _)| *value);
+
+        match best_dig {
+            Some((value, play)) if value > 0 => Draw::Dig(play),
+            // The stock can be gone when play carries on from the discard pile
+            _ if !draws.iter().any(|draw| matches!(draw, Draw::Stock)) => Draw::TopDiscard,
+            _ => Draw::Stock,
+        }
+    }
+
+    fn choose_play(&mut self, view: &PlayerView) -> Option<Play> {
+        view.legal_plays().into_iter().max_by_key(|play| play.points(view.rules()))
+    }
+
+    fn choose_discard(&mut self, view: &PlayerView) -> Card {
+        let hand = view.hand();
+        if view.rules().game == GameKind::Gin {
+            return best_gin_discard(&hand.distinct()).1;
+        }
+        let cards = hand.as_ordered_list();
+        let loose: Vec<Card> = cards.iter().copied().filter(|card| !has_partner(card, hand)).collect();
+        let candidates = if loose.is_empty() { &cards } else { &loose };
+        *candidates
+            .iter()
+            .max_by_key(|card| score::card_points(card))
+            .expect("Invalid game state: nothing in hand to discard")
+    }
+}
+
+fn gin_draw(view: &PlayerView) -> Draw {
+    let draws = view.legal_draws();
+    let can_draw_stock = draws.iter().any(|draw| matches!(draw, Draw::Stock));
+    let top = match view.discard_pile().last() {
+        Some(top) if draws.iter().any(|draw| matches!(draw, Draw::TopDiscard)) => top,
+        _ => return Draw::Stock,
+    };
+
+    let hand = view.hand().distinct();
+    let mut with_top = hand;
+    with_top.add(top);
+    let (deadwood, discard) = best_gin_discard(&with_top);
+    let improves = deadwood < Arrangement::best(&hand).deadwood_points() && discard != *top;
+    if improves || !can_draw_stock { Draw::TopDiscard } else { Draw::Stock }
+}
+
+// The discard that leaves the least deadwood, and how much that is. Ties go
+// to throwing away the bigger card
+fn best_gin_discard(hand: &CardSet) -> (u32, Card) {
+    gin::deadwood_after_discards(hand)
+        .into_iter()
+        .map(|(card, deadwood)| (deadwood, card))
+        .min_by_key(|(deadwood, card)| (*deadwood, Reverse(gin::deadwood_value(card))))
+        .expect("Invalid game state: nothing in hand to discard")
+}
+
+// Points from the play minus what the extra cards cost if they get stuck
+pub fn dig_value(play: &Play, rules: &RuleSet) -> i32 {
+    let mut extra_cards = play.cards_acquired.clone();
+    for card in play.cards_used.as_ordered_list().iter() {
+        if extra_cards.contains(card) {
+            extra_cards.remove(card);
+        }
+    }
+    play.points(rules) as i32 - score::hand_points(&extra_cards, rules) as i32
+}
+
+// Another card of the same value, or one next to it in the same suit
+fn has_partner(card: &Card, hand: &CardMultiset) -> bool {
+    hand.as_ordered_list().iter().any(|other| {
+        let distance = (card.value.index() as i32 - other.value.index() as i32).abs();
+        other != card && (other.value == card.value || (other.suit == card.suit && distance == 1))
+    })
+}
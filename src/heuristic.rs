@@ -0,0 +1,271 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::card::{Card, CardMultiset};
+use crate::game::{Draw, PlayerView, Strategy};
+use crate::rules::RuleSet;
+use crate::score::{self, Play};
+
+// Like the greedy bot but with its judgement calls turned into numbers, so
+// they can be tuned (see tuning.rs). The defaults play a lot like greedy.
+//
+// Every card in hand gets a keep value: something for each card it's close to
+// making a meld with, minus what it costs if it's still in hand at the end.
+// The card with the lowest keep value gets discarded, and the top discard gets
+// picked up when its keep value would be high enough.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeuristicParams {
+    // For each card of the same value, or next to it in the same suit
+    pub near_meld_value: f64,
+    // For each card two away in the same suit (a gap a run could fill)
+    pub gap_meld_value: f64,
+    // Per point a card would cost left in hand
+    pub deadwood_penalty: f64,
+    // Added to what a dig looks worth. Above 0 digs more often
+    pub dig_aggressiveness: f64,
+    // Keep value the top discard needs to be worth taking instead of the stock
+    pub top_discard_threshold: f64,
+}
+
+impl Default for HeuristicParams {
+    fn default() -> Self {
+        HeuristicParams {
+            near_meld_value: 10.0,
+            gap_meld_value: 4.0,
+            deadwood_penalty: 1.0,
+            dig_aggressiveness: 0.0,
+            top_discard_threshold: 15.0,
+        }
+    }
+}
+
+impl HeuristicParams {
+    // Same order as to_vec
+    pub const NAMES: [&'static str; 5] = [
+        "near_meld_value",
+        "gap_meld_value",
+        "deadwood_penalty",
+        "dig_aggressiveness",
+        "top_discard_threshold",
+    ];
+
+    pub fn to_vec(&self) -> Vec<f64> {
+        vec![
+            self.near_meld_value,
+            self.gap_meld_value,
+            self.deadwood_penalty,
+            self.dig_aggressiveness,
+            self.top_discard_threshold,
+        ]
+    }
+
+    pub fn from_slice(values: &[f64]) -> Self {
+        if values.len() != Self::NAMES.len() {
+            panic!("Expected {} parameters, got {}", Self::NAMES.len(), values.len());
+        }
+        HeuristicParams {
+            near_meld_value: values[0],
+            gap_meld_value: values[1],
+            deadwood_penalty: values[2],
+            dig_aggressiveness: values[3],
+            top_discard_threshold: values[4],
+        }
+    }
+
+    // One "name value" per line. Lines starting with # are comments, and
+    // anything left out keeps its default
+    pub fn to_text(&self) -> String {
+        Self::NAMES
+            .iter()
+            .zip(self.to_vec())
+            .map(|(name, value)| format!("{} {}\n", name, value))
+            .collect()
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut values = HeuristicParams::default().to_vec();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line
+                .split_once(char::is_whitespace)
+                .ok_or(format!("Expected \"name value\", got \"{}\"", line))?;
+            let index = Self::NAMES
+                .iter()
+                .position(|n| *n == name)
+                .ok_or(format!("Unknown parameter \"{}\"", name))?;
+            // NaN and infinity parse fine but would wreck every comparison
+            values[index] = match value.trim().parse::<f64>() {
+                Ok(number) if number.is_finite() => number,
+                _ => return Err(format!("Invalid value for {}: \"{}\"", name, value.trim())),
+            };
+        }
+        Ok(HeuristicParams::from_slice(&values))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        HeuristicParams::from_text(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct HeuristicStrategy {
+    pub params: HeuristicParams,
+}
+
+impl HeuristicStrategy {
+    pub fn new(params: HeuristicParams) -> Self {
+        HeuristicStrategy { params }
+    }
+
+    // How much a card is worth holding on to, given the rest of the hand
+    pub fn keep_value(&self, card: &Card, hand: &CardMultiset) -> f64 {
+        let mut value = -self.params.deadwood_penalty * score::card_points(card) as f64;
+        for other in hand.as_ordered_list().iter().filter(|other| *other != card) {
+            let distance = (card.value.index() as i32 - other.value.index() as i32).abs();
+            if other.value == card.value || (other.suit == card.suit && distance == 1) {
+                value += self.params.near_meld_value;
+            } else if other.suit == card.suit && distance == 2 {
+                value += self.params.gap_meld_value;
+            }
+        }
+        value
+    }
+
+    fn dig_value(&self, play: &Play, rules: &RuleSet) -> f64 {
+        let mut extra_cards = play.cards_acquired.clone();
+        for card in play.cards_used.as_ordered_list().iter() {
+            if extra_cards.contains(card) {
+                extra_cards.remove(card);
+            }
+        }
+        play.points(rules) as f64
+            - self.params.deadwood_penalty * score::hand_points(&extra_cards, rules) as f64
+            + self.params.dig_aggressiveness
+    }
+}
+
+impl Strategy for HeuristicStrategy {
+    fn choose_draw(&mut self, view: &PlayerView) -> Draw {
+        let draws = view.legal_draws();
+        let best_dig = draws
+            .iter()
+            .filter_map(|draw| match draw {
+                Draw::Dig(play) => Some((self.dig_value(play, view.rules()), play)),
+                _ => None,
+            })
+            .max_by(|a, b| a.0.total_cmp(&b.0));
+        if let Some((value, play)) = best_dig {
+            if value > 0.0 {
+                return Draw::Dig(play.clone());
+            }
+        }
+
+        let can_take_top = draws.iter().any(|draw| matches!(draw, Draw::TopDiscard));
+        let can_draw_stock = draws.iter().any(|draw| matches!(draw, Draw::Stock));
+        if can_take_top {
+            let top = view.discard_pile().last().unwrap();
+            if !can_draw_stock || self.keep_value(top, view.hand()) > self.params.top_discard_threshold {
+                return Draw::TopDiscard;
+            }
+        }
+        Draw::Stock
+    }
+
+    fn choose_play(&mut self, view: &PlayerView) -> Option<Play> {
+        view.legal_plays().into_iter().max_by_key(|play| play.points(view.rules()))
+    }
+
+    fn choose_discard(&mut self, view: &PlayerView) -> Card {
+        let hand = view.hand();
+        hand.as_ordered_list()
+            .into_iter()
+            .map(|card| (self.keep_value(&card, hand), card))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, card)| card)
+            .expect("Invalid game state: nothing in hand to discard")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameState;
+
+    fn tweaked() -> HeuristicParams {
+        HeuristicParams {
+            near_meld_value: 7.5,
+            gap_meld_value: -1.25,
+            deadwood_penalty: 0.3,
+            dig_aggressiveness: 12.0,
+            top_discard_threshold: 1e-3,
+        }
+    }
+
+    #[test]
+    fn text_round_trips() {
+        assert_eq!(HeuristicParams::from_text(&tweaked().to_text()), Ok(tweaked()));
+    }
+
+    #[test]
+    fn comments_are_skipped_and_missing_values_keep_their_defaults() {
+        let params = HeuristicParams::from_text("# tuned\n\n  near_meld_value   3\n").unwrap();
+        assert_eq!(params, HeuristicParams { near_meld_value: 3.0, ..HeuristicParams::default() });
+    }
+
+    #[test]
+    fn bad_lines_are_errors() {
+        assert!(HeuristicParams::from_text("bogus 1").is_err());
+        assert!(HeuristicParams::from_text("near_meld_value").is_err());
+        assert!(HeuristicParams::from_text("near_meld_value lots").is_err());
+    }
+
+    #[test]
+    fn non_finite_values_are_rejected() {
+        assert!(HeuristicParams::from_text("near_meld_value NaN").is_err());
+        assert!(HeuristicParams::from_text("gap_meld_value inf").is_err());
+        assert!(HeuristicParams::from_text("deadwood_penalty -inf").is_err());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join(format!("rummy-sim-heuristic-{}.txt", std::process::id()));
+        tweaked().save(&path).unwrap();
+        let loaded = HeuristicParams::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), tweaked());
+    }
+
+    fn holding(hand: &str) -> GameState {
+        let cards: Vec<Card> = hand.split_whitespace().map(|c| c.parse().unwrap()).collect();
+        let mut state = GameState::deal(2, 0, &RuleSet::default(), 0).unwrap();
+        let player = state.current_player;
+        state.hands[player] = CardMultiset::from_vec(&cards);
+        state.has_drawn = true;
+        state
+    }
+
+    #[test]
+    fn discards_the_card_least_worth_keeping() {
+        let state = holding("5:H 6:H 7:C K:S");
+        let card = HeuristicStrategy::default().choose_discard(&state.view(state.current_player));
+        assert_eq!(card.to_string(), "K:S");
+    }
+
+    #[test]
+    fn nan_params_dont_panic() {
+        let params = HeuristicParams { near_meld_value: f64::NAN, ..HeuristicParams::default() };
+        let state = holding("5:H 6:H 7:C K:S");
+        let view = state.view(state.current_player);
+        let card = HeuristicStrategy::new(params).choose_discard(&view);
+        assert!(view.hand().contains(&card));
+    }
+}
@@ -0,0 +1,331 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+use crate::card::Card;
+use crate::game::{Action, Draw, PlayerView, Strategy};
+use crate::greedy::GreedyStrategy;
+use crate::monte_carlo::{determinize, margin, rollout};
+use crate::score::{Play, PlayKind};
+
+// Information Set Monte Carlo Tree Search, single observer version.
+//
+// Like the flat Monte Carlo bot, every iteration starts by making up a world
+// that fits what the bot can see. The difference is that the search keeps a
+// tree of whole action sequences (everyone's, not just the bot's) and goes
+// down it with UCB, so it can look at what happens after the next decision too.
+// Different worlds allow different actions, especially for the other players,
+// so a child only counts as "available" when it's legal in the current world
+// and UCB uses how often it was available rather than the parent's visits.
+
+#[derive(Clone, Copy)]
+pub struct IsmctsConfig {
+    pub iterations: usize,
+    // UCB exploration constant. Rewards are point margins divided by
+    // REWARD_SCALE so something around 0.7 is a sensible start
+    pub exploration: f64,
+    pub max_rollout_turns: usize,
+}
+
+impl Default for IsmctsConfig {
+    fn default() -> Self {
+        IsmctsConfig {
+            iterations: 1000,
+            exploration: 0.7,
+            max_rollout_turns: 100,
+        }
+    }
+}
+
+// A 100 point swing in a round counts as 1
+const REWARD_SCALE: f64 = 100.0;
+
+pub struct IsmctsBot<P: Strategy + Clone = GreedyStrategy> {
+    pub config: IsmctsConfig,
+    // Used by everyone once a playout leaves the tree
+    pub rollout_policy: P,
+    rng: StdRng,
+    // Searching for a play can decide to end the turn instead, in which case
+    // the discard's already been picked
+    pending_discard: Option<Card>,
+    // Same for a discard that was picked as a knock
+    pending_knock: Option<Card>,
+}
+
+impl IsmctsBot<GreedyStrategy> {
+    pub fn new(config: IsmctsConfig, seed: u64) -> Self {
+        IsmctsBot::with_rollout_policy(config, GreedyStrategy, seed)
+    }
+}
+
+impl<P: Strategy + Clone> IsmctsBot<P> {
+    pub fn with_rollout_policy(config: IsmctsConfig, rollout_policy: P, seed: u64) -> Self {
+        IsmctsBot {
+            config,
+            rollout_policy,
+            rng: StdRng::seed_from_u64(seed),
+            pending_discard: None,
+            pending_knock: None,
+        }
+    }
+
+    // Runs the search from the current state and returns the root's children
+    // as (action, visits)
+    pub fn search(&mut self, view: &PlayerView) -> Vec<(ActionKey, u32)> {
+        let model = view.opponent_model();
+        let mut tree = vec![Node::root()];
+
+        for _ in 0..self.config.iterations {
+            let mut world = determinize(view, &model, &mut self.rng);
+            let mut path = vec![0];
+            let mut node = 0;
+
+            // Select, then expand one new node
+            loop {
+                let legal = world.legal_actions();
+                if legal.is_empty() {
+                    break;
+                }
+                let legal_keys: Vec<ActionKey> = legal.iter().map(ActionKey::of).collect();
+
+                let mut available = vec![];
+                for child in tree[node].children.clone() {
+                    if let Some(i) = legal_keys.iter().position(|key| *key == tree[child].action) {
+                        tree[child].availability += 1;
+                        available.push((child, i));
+                    }
+                }
+
+                let player = world.current_player;
+                let untried: Vec<usize> = (0..legal.len())
+                    .filter(|i| !available.iter().any(|(_, j)| j == i))
+                    .collect();
+                if let Some(&i) = untried.choose(&mut self.rng) {
+                    let child = tree.len();
+                    tree.push(Node::new(legal_keys[i].clone(), player));
+                    tree[node].children.push(child);
+                    world.apply_action(&legal[i]);
+                    path.push(child);
+                    break;
+                }
+
+                let (child, i) = *available
+                    .iter()
+                    .max_by(|a, b| {
+                        let a = tree[a.0].ucb(self.config.exploration);
+                        let b = tree[b.0].ucb(self.config.exploration);
+                        a.partial_cmp(&b).unwrap()
+                    })
+                    .unwrap();
+                world.apply_action(&legal[i]);
+                path.push(child);
+                node = child;
+            }
+
+            let mut policy = self.rollout_policy.clone();
+            let scores = rollout(&mut world, &mut policy, self.config.max_rollout_turns);
+
+            for &i in path.iter().skip(1) {
+                tree[i].visits += 1;
+                tree[i].total_reward += margin(&scores, tree[i].player) / REWARD_SCALE;
+            }
+        }
+
+        tree[0].children.iter().map(|&child| (tree[child].action.clone(), tree[child].visits)).collect()
+    }
+
+    fn best_action<F: Fn(&ActionKey) -> bool>(&mut self, view: &PlayerView, wanted: F) -> Option<ActionKey> {
+        self.search(view)
+            .into_iter()
+            .filter(|(key, _)| wanted(key))
+            .max_by_key(|(_, visits)| *visits)
+            .map(|(key, _)| key)
+    }
+}
+
+impl<P: Strategy + Clone> Strategy for IsmctsBot<P> {
+    fn choose_draw(&mut self, view: &PlayerView) -> Draw {
+        self.pending_discard = None;
+        let best = self.best_action(view, |key| key.is_draw());
+        view.legal_draws()
+            .into_iter()
+            .find(|draw| best == Some(ActionKey::of_draw(draw)))
+            .unwrap_or_else(|| GreedyStrategy.choose_draw(view))
+    }
+
+    fn choose_play(&mut self, view: &PlayerView) -> Option<Play> {
+        if view.legal_plays().is_empty() {
+            return None;
+        }
+        match self.best_action(view, |_| true) {
+            Some(ActionKey::Discard(card)) => {
+                self.pending_discard = Some(card);
+                None
+            },
+            best => view
+                .legal_plays()
+                .into_iter()
+                .find(|play| best == Some(ActionKey::Play(PlayKey::of(play)))),
+        }
+    }
+
+    fn choose_discard(&mut self, view: &PlayerView) -> Card {
+        self.pending_knock = None;
+        if let Some(card) = self.pending_discard.take() {
+            if view.hand().contains(&card) {
+                return card;
+            }
+        }
+        match self.best_action(view, |key| matches!(key, ActionKey::Discard(_) | ActionKey::Knock(_))) {
+            Some(ActionKey::Discard(card)) => card,
+            Some(ActionKey::Knock(card)) => {
+                self.pending_knock = Some(card);
+                card
+            },
+            _ => GreedyStrategy.choose_discard(view),
+        }
+    }
+
+    fn choose_knock(&mut self, _view: &PlayerView, card: &Card) -> bool {
+        self.pending_knock.take() == Some(*card)
+    }
+}
+
+// Enough to tell actions apart without holding on to whole plays
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum ActionKey {
+    Stock,
+    TopDiscard,
+    Dig(PlayKey),
+    Play(PlayKey),
+    Discard(Card),
+    DiscardJoker,
+    Knock(Card),
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct PlayKey {
+    pub cards_used: u64,
+    pub is_run: bool,
+    // What each wild stands for, as card bits
+    pub wilds: u64,
+}
+
+impl ActionKey {
+    pub fn of(action: &Action) -> ActionKey {
+        match action {
+            Action::Draw(draw) => ActionKey::of_draw(draw),
+            Action::Play(play) => ActionKey::Play(PlayKey::of(play)),
+            Action::Discard(card) => ActionKey::Discard(*card),
+            Action::DiscardJoker => ActionKey::DiscardJoker,
+            Action::Knock(card) => ActionKey::Knock(*card),
+        }
+    }
+
+    pub fn of_draw(draw: &Draw) -> ActionKey {
+        match draw {
+            Draw::Stock => ActionKey::Stock,
+            Draw::TopDiscard => ActionKey::TopDiscard,
+            Draw::Dig(play) => ActionKey::Dig(PlayKey::of(play)),
+        }
+    }
+
+    fn is_draw(&self) -> bool {
+        matches!(self, ActionKey::Stock | ActionKey::TopDiscard | ActionKey::Dig(_))
+    }
+}
+
+impl PlayKey {
+    pub fn of(play: &Play) -> PlayKey {
+        let wilds = play
+            .wilds
+            .iter()
+            .fold(0, |bits, w| bits | (1 << crate::card::bit_of(&w.stands_for)));
+        PlayKey {
+            cards_used: play.cards_used.bits(),
+            is_run: matches!(play.kind, PlayKind::StraightFlush { .. }),
+            wilds,
+        }
+    }
+}
+
+struct Node {
+    // The action that led here, and who took it
+    action: ActionKey,
+    player: usize,
+    children: Vec<usize>,
+    visits: u32,
+    availability: u32,
+    total_reward: f64,
+}
+
+impl Node {
+    fn root() -> Self {
+        Node::new(ActionKey::Stock, 0)
+    }
+
+    fn new(action: ActionKey, player: usize) -> Self {
+        Node {
+            action,
+            player,
+            children: vec![],
+            visits: 0,
+            availability: 1,
+            total_reward: 0.0,
+        }
+    }
+
+    fn ucb(&self, exploration: f64) -> f64 {
+        let visits = self.visits.max(1) as f64;
+        let mean = self.total_reward / visits;
+        mean + exploration * ((self.availability as f64).ln() / visits).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{self, GameState};
+    use crate::rules::RuleSet;
+
+    fn small_config() -> IsmctsConfig {
+        IsmctsConfig { iterations: 60, max_rollout_turns: 20, ..IsmctsConfig::default() }
+    }
+
+    #[test]
+    fn root_children_are_the_legal_actions() {
+        let state = GameState::deal(2, 0, &RuleSet::default(), 4).unwrap();
+        let view = state.view(state.current_player);
+        let legal: Vec<ActionKey> = view.legal_actions().iter().map(ActionKey::of).collect();
+
+        let children = IsmctsBot::new(small_config(), 0).search(&view);
+        assert!(children.iter().all(|(key, _)| legal.contains(key)));
+        // Every iteration goes through exactly one root child
+        assert_eq!(children.iter().map(|(_, visits)| visits).sum::<u32>(), 60);
+    }
+
+    #[test]
+    fn same_seed_same_search() {
+        let state = GameState::deal(3, 0, &RuleSet::default(), 4).unwrap();
+        let view = state.view(state.current_player);
+        let first = IsmctsBot::new(small_config(), 9).search(&view);
+        let second = IsmctsBot::new(small_config(), 9).search(&view);
+        assert!(first == second);
+    }
+
+    #[test]
+    fn seeded_rounds_are_legal_and_repeatable() {
+        let play = |seed: u64| {
+            let mut state = GameState::deal(2, 0, &RuleSet::default(), 11).unwrap();
+            state.turn_cap = 12;
+            let mut bots: Vec<Box<dyn Strategy>> = vec![
+                Box::new(IsmctsBot::new(small_config(), seed)),
+                Box::new(GreedyStrategy),
+            ];
+            // play_round panics on anything illegal
+            let scores = game::play_round(&mut state, &mut bots);
+            (scores, state.history.len())
+        };
+        assert_eq!(play(3), play(3));
+    }
+}
@@ -0,0 +1,160 @@
+use serde::Deserialize;
+
+use crate::card::{Card, CardSet};
+use crate::game::Strategy;
+use crate::game_match::MatchConfig;
+use crate::rules::RuleSet;
+use crate::score::{self, PlayedCards};
+use crate::simulation;
+
+// JSON front door to the play generator, shared by anything that wants to ask
+// "what can I do from here" without linking against the rust types directly.
+//
+// Request looks like:
+// {
+//     "hand": [{"suit": "Spades", "value": "Ace"}, ...],
+//     "discard_pile": [...],   (bottom of the pile first)
+//     "played_cards": {"straight_flush_played": [...], "multiple_played": [...]},
+//     "rules": {"min_run_size": 4, ...}   (optional, anything left out is the default)
+// }
+//
+// Response is the list of plays, same shape as score::Play
+//
+// Simulations take a SimulationRequest:
+// {
+//     "strategies": ["greedy", "monte_carlo"],   (one per seat, see simulation::STRATEGY_NAMES)
+//     "num_matches": 100,
+//     "seed": 1,             (optional)
+//     "rules": {...},        (optional)
+//     "match_config": {"max_rounds": 50, ...},   (optional)
+//     "confidence": 0.95     (optional, for the intervals)
+// }
+// and give back the totals plus a summary per seat
+
+// Anything much bigger should be run from rust (or python) rather than
+// holding up the server
+pub const MAX_SIMULATION_MATCHES: usize = 10_000;
+
+#[derive(Deserialize)]
+pub struct Position {
+    pub hand: CardSet,
+    pub discard_pile: Vec<Card>,
+    pub played_cards: PlayedCards,
+    #[serde(default)]
+    pub rules: RuleSet,
+}
+
+pub fn plays_for_position(input: &str) -> Result<String, String> {
+    let position = parse_position(input)?;
+
+    position.validate()?;
+
+    let plays = score::all_possible_plays(
+        &position.hand,
+        &position.discard_pile,
+        &position.played_cards,
+        &position.rules,
+    );
+    serde_json::to_string(&plays).map_err(|e| e.to_string())
+}
+
+// Err only when the input isn't a position at all. A position that parses
+// but couldn't happen comes back as {"valid": false, "error": "..."}
+pub fn validate_position(input: &str) -> Result<String, String> {
+    let position = parse_position(input)?;
+    let response = match position.validate() {
+        Ok(()) => serde_json::json!({ "valid": true }),
+        Err(e) => serde_json::json!({ "valid": false, "error": e }),
+    };
+    Ok(response.to_string())
+}
+
+impl Position {
+    // A position doesn't say how many are playing, so it's one deck unless the
+    // rules say otherwise
+    fn validate(&self) -> Result<(), String> {
+        let num_decks = self.rules.num_decks.unwrap_or(1);
+        score::validate_position(&self.hand, &self.discard_pile, &self.played_cards, &self.rules, num_decks)
+    }
+}
+
+fn parse_position(input: &str) -> Result<Position, String> {
+    serde_json::from_str(input).map_err(|e| format!("Invalid position: {}", e))
+}
+
+#[derive(Deserialize)]
+pub struct SimulationRequest {
+    pub strategies: Vec<String>,
+    pub num_matches: usize,
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default)]
+    pub rules: RuleSet,
+    #[serde(default)]
+    pub match_config: MatchConfig,
+    #[serde(default = "default_confidence")]
+    pub confidence: f64,
+}
+
+fn default_confidence() -> f64 {
+    0.95
+}
+
+pub fn simulate(input: &str) -> Result<String, String> {
+    let request: SimulationRequest = serde_json::from_str(input)
+        .map_err(|e| format!("Invalid simulation request: {}", e))?;
+
+    let num_players = request.strategies.len();
+    if num_players < 2 {
+        return Err("Need at least 2 strategies, one per seat".to_string());
+    }
+    if request.num_matches == 0 || request.num_matches > MAX_SIMULATION_MATCHES {
+        return Err(format!("Between 1 and {} matches per request", MAX_SIMULATION_MATCHES));
+    }
+    if !(request.confidence > 0.0 && request.confidence < 1.0) {
+        return Err(format!("Confidence should be between 0 and 1, got {}", request.confidence));
+    }
+    // Catches typos before anything gets played
+    for name in request.strategies.iter() {
+        simulation::strategy_by_name(name, 0, 0)?;
+    }
+
+    let report = simulation::simulate_matches(
+        num_players,
+        &request.rules,
+        request.match_config,
+        request.num_matches,
+        request.seed,
+        |seed| {
+            request
+                .strategies
+                .iter()
+                .enumerate()
+                .map(|(seat, name)| simulation::strategy_by_name(name, seat, seed).unwrap())
+                .collect::<Vec<Box<dyn Strategy>>>()
+        },
+    )?;
+
+    let seats: Vec<serde_json::Value> = request
+        .strategies
+        .iter()
+        .enumerate()
+        .map(|(seat, name)| {
+            serde_json::json!({
+                "strategy": name,
+                "wins": report.wins[seat],
+                "win_rate": report.win_rate(seat),
+                "win_rate_interval": report.win_rate_interval(seat, request.confidence),
+                "mean_score": report.mean_score(seat),
+                "mean_score_interval": report.mean_score_interval(seat, request.confidence),
+            })
+        })
+        .collect();
+    let response = serde_json::json!({
+        "matches": report.matches,
+        "rounds": report.rounds,
+        "turn_capped_rounds": report.turn_capped_rounds,
+        "seats": seats,
+    });
+    Ok(response.to_string())
+}
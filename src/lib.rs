@@ -1,2 +1,35 @@
+pub mod analysis;
 pub mod card;
+pub mod contract;
+pub mod deck;
+pub mod duplicate;
+pub mod endgame;
+pub mod export;
+pub mod features;
+pub mod game;
+pub mod game_match;
+pub mod gin;
+pub mod greedy;
+pub mod heuristic;
+pub mod ismcts;
+pub mod meld;
+pub mod mlp;
+pub mod monte_carlo;
+pub mod opponent;
+pub mod pickup;
+pub mod rules;
 pub mod score;
+pub mod self_play;
+pub mod simulation;
+pub mod stats;
+pub mod tournament;
+pub mod tuning;
+
+#[cfg(feature = "json")]
+pub mod json;
+
+#[cfg(feature = "python")]
+mod python;
+
+#[cfg(feature = "wasm")]
+mod wasm;
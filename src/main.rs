@@ -1,4 +1,5 @@
 use rummy_sim::card::{CardSet, Card, CardSuit, CardValue};
+use rummy_sim::rules::RuleSet;
 use rummy_sim::score;
 
 fn make_card(value_index: usize, suit: CardSuit) -> Card {
@@ -16,6 +17,7 @@ fn print_play(play: score::Play) {
     println!("({:?}, {}, {})", play.kind, play.cards_used, play.cards_acquired);
 }
 
+#[allow(dead_code)]
 fn print_test_possible_plays1() {
     use CardSuit::*;
 
@@ -50,9 +52,10 @@ fn print_test_possible_plays1() {
             (10, Spades),
             (10, Diamonds),
         ])),
+        ..Default::default()
     };
     
-    let plays = score::all_possible_plays(&hand, &discard_pile, &played_cards);
+    let plays = score::all_possible_plays(&hand, &discard_pile, &played_cards, &RuleSet::default());
 
     println!("PLAYS:");
     for play in plays {
@@ -60,6 +63,7 @@ fn print_test_possible_plays1() {
     }
 }
 
+#[allow(dead_code)]
 fn print_test_possible_plays2() {
     use CardSuit::*;
 
@@ -120,9 +124,10 @@ fn print_test_possible_plays2() {
             (3, Diamonds),
         ])),
         multiple_played: CardSet::new(),
+        ..Default::default()
     };
     
-    let plays = score::all_possible_plays(&hand, &discard_pile, &played_cards);
+    let plays = score::all_possible_plays(&hand, &discard_pile, &played_cards, &RuleSet::default());
 
     println!("PLAYS:");
     for play in plays {
@@ -177,9 +182,10 @@ fn print_test_possible_plays3() {
             (7, Spades),
         ])),
         multiple_played: CardSet::new(),
+        ..Default::default()
     };
     
-    let plays = score::all_possible_plays(&hand, &discard_pile, &played_cards);
+    let plays = score::all_possible_plays(&hand, &discard_pile, &played_cards, &RuleSet::default());
 
     println!("PLAYS:");
     for play in plays {
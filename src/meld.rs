@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+
+use crate::card::{bit_of, Card, CardSet, NUM_CARD_VALUES, NUM_SUITS};
+use crate::rules::RuleSet;
+use crate::score::{self, PlayedCards};
+
+// Splitting a hand into non-overlapping melds so the leftover cards are worth
+// as little as possible. all_possible_plays already finds every meld a hand
+// could make on its own (with nothing on the table and no discard pile), so
+// those are the candidates and this just picks the best combination of them.
+//
+// Search goes: take the lowest card still left. Either it's deadwood, or it's
+// in one of the candidate melds that only uses cards still left. Results get
+// memoized on the set of cards left, which keeps 10-ish card hands instant.
+
+pub struct Partition {
+    pub melds: Vec<CardSet>,
+    pub deadwood: CardSet,
+    pub deadwood_points: u32,
+}
+
+pub fn best_partition(
+    hand: &CardSet,
+    rules: &RuleSet,
+    card_points: impl Fn(&Card) -> u32,
+) -> Partition {
+    let mut solver = Solver::new(hand, rules, card_points);
+    let hand_mask = hand.bits();
+    let deadwood_points = solver.solve(hand_mask);
+
+    // Walk the memo back down to get which melds were actually picked
+    let mut melds = vec![];
+    let mut remaining = hand_mask;
+    while remaining != 0 {
+        let (_, choice) = solver.memo[&remaining];
+        match choice {
+            Some(i) => {
+                let meld = solver.candidates[i];
+                melds.push(CardSet::from_bits(meld));
+                remaining &= !meld;
+            },
+            None => remaining &= remaining - 1,
+        }
+    }
+
+    let mut deadwood = *hand;
+    for meld in melds.iter() {
+        for card in meld.as_ordered_list().iter() {
+            deadwood.remove(card);
+        }
+    }
+
+    Partition { melds, deadwood, deadwood_points }
+}
+
+// Least deadwood points left with each card of the hand taken out in turn,
+// for picking a discard. Every meld of what's left is a meld of the whole
+// hand, so they all share one solver and it's not much slower than one
+// best_partition
+pub fn least_deadwood_without_each(
+    hand: &CardSet,
+    rules: &RuleSet,
+    card_points: impl Fn(&Card) -> u32,
+) -> Vec<(Card, u32)> {
+    let mut solver = Solver::new(hand, rules, card_points);
+    hand.as_ordered_list()
+        .into_iter()
+        .map(|card| (card, solver.solve(hand.bits() & !(1 << bit_of(&card)))))
+        .collect()
+}
+
+struct Solver {
+    candidates: Vec<u64>,
+    // Which candidates each card (by bit) shows up in
+    candidates_by_card: Vec<Vec<usize>>,
+    points: [u32; NUM_SUITS * NUM_CARD_VALUES],
+    // remaining cards -> (best deadwood, candidate meld used for the lowest card)
+    memo: HashMap<u64, (u32, Option<usize>)>,
+}
+
+impl Solver {
+    fn new(hand: &CardSet, rules: &RuleSet, card_points: impl Fn(&Card) -> u32) -> Self {
+        // Wilds aren't handled here, so only plays made of real cards count
+        let candidates: Vec<u64> = score::all_possible_plays(hand, &[], &PlayedCards::default(), rules)
+            .iter()
+            .filter(|play| play.wilds.is_empty())
+            .map(|play| play.cards_used.bits())
+            .collect();
+
+        let mut points = [0; NUM_SUITS * NUM_CARD_VALUES];
+        for card in hand.as_ordered_list().iter() {
+            points[bit_of(card)] = card_points(card);
+        }
+
+        let mut candidates_by_card = vec![vec![]; NUM_SUITS * NUM_CARD_VALUES];
+        for (i, meld) in candidates.iter().enumerate() {
+            for (bit, candidate_list) in candidates_by_card.iter_mut().enumerate() {
+                if meld & (1 << bit) != 0 {
+                    candidate_list.push(i);
+                }
+            }
+        }
+
+        Solver { candidates, candidates_by_card, points, memo: HashMap::new() }
+    }
+
+    fn solve(&mut self, remaining: u64) -> u32 {
+        if remaining == 0 {
+            return 0;
+        }
+        if let Some((best, _)) = self.memo.get(&remaining) {
+            return *best;
+        }
+
+        let lowest_bit = remaining.trailing_zeros() as usize;
+        let lowest = 1u64 << lowest_bit;
+
+        // Melds first, since finding a zero early means nothing else needs looking at
+        let mut best = u32::MAX;
+        let mut best_choice = None;
+        for j in 0..self.candidates_by_card[lowest_bit].len() {
+            let i = self.candidates_by_card[lowest_bit][j];
+            let meld = self.candidates[i];
+            if meld & remaining != meld {
+                continue;
+            }
+            let deadwood = self.solve(remaining & !meld);
+            if deadwood < best {
+                best = deadwood;
+                best_choice = Some(i);
+            }
+            if best == 0 {
+                break;
+            }
+        }
+
+        if best > 0 {
+            let deadwood = self.points[lowest_bit] + self.solve(remaining & !lowest);
+            if deadwood < best {
+                best = deadwood;
+                best_choice = None;
+            }
+        }
+
+        self.memo.insert(remaining, (best, best_choice));
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gin;
+
+    fn cards(s: &str) -> CardSet {
+        let cards: Vec<Card> = s.split_whitespace().map(|c| c.parse().unwrap()).collect();
+        CardSet::from_vec(&cards)
+    }
+
+    #[test]
+    fn leftover_cards_are_the_deadwood() {
+        let hand = cards("A:S 2:S 3:S 4:H 5:H 6:H 7:C 7:D 7:S 2:C K:D");
+        let partition = best_partition(&hand, &gin::rules(), gin::deadwood_value);
+        assert_eq!(partition.melds.len(), 3);
+        assert!(partition.deadwood == cards("2:C K:D"));
+        assert_eq!(partition.deadwood_points, 12);
+    }
+
+    #[test]
+    fn overlapping_melds_keep_the_cheaper_leftovers() {
+        // The run leaves 14 points, the set of sevens only 11
+        let hand = cards("5:H 6:H 7:H 7:S 7:C");
+        let partition = best_partition(&hand, &gin::rules(), gin::deadwood_value);
+        assert_eq!(partition.melds.len(), 1);
+        assert!(partition.melds[0] == cards("7:H 7:S 7:C"));
+        assert!(partition.deadwood == cards("5:H 6:H"));
+        assert_eq!(partition.deadwood_points, 11);
+    }
+
+    #[test]
+    fn removing_each_card_matches_a_fresh_partition() {
+        let hand = cards("A:S 2:S 3:S 4:S 4:H 5:H 6:H 7:C 7:D 7:S 9:C");
+        let rules = gin::rules();
+        for (card, points) in least_deadwood_without_each(&hand, &rules, gin::deadwood_value) {
+            let mut rest = hand;
+            rest.remove(&card);
+            assert_eq!(points, best_partition(&rest, &rules, gin::deadwood_value).deadwood_points);
+        }
+    }
+}
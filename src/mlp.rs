@@ -0,0 +1,365 @@
+use std::fs;
+use std::path::Path;
+
+use crate::card::Card;
+use crate::features::{self, ACTION_LEN, FEATURE_LEN};
+use crate::game::{Action, Draw, GameEvent, PlayerView, Strategy};
+use crate::score::Play;
+
+// Running a small trained network inside the simulator, no python needed.
+//
+// The network takes features::encode followed by features::encode_action
+// (the same layout self_play writes) and gives back one number, how good the
+// action looks. MlpStrategy scores every legal action at each decision and
+// takes the best.
+//
+// Weights file, plain text, whitespace separated:
+//     # comments are fine anywhere
+//     layer <inputs> <outputs> <relu|tanh|sigmoid|linear>
+//     <outputs lines of inputs weights each>
+//     <one line of outputs biases>
+//     layer ...
+// which is just each torch Linear's weight and bias written out in order.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Activation {
+    Relu,
+    Tanh,
+    Sigmoid,
+    Linear,
+}
+
+impl Activation {
+    fn apply(&self, x: f32) -> f32 {
+        match self {
+            Activation::Relu => x.max(0.0),
+            Activation::Tanh => x.tanh(),
+            Activation::Sigmoid => 1.0 / (1.0 + (-x).exp()),
+            Activation::Linear => x,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Activation::Relu => "relu",
+            Activation::Tanh => "tanh",
+            Activation::Sigmoid => "sigmoid",
+            Activation::Linear => "linear",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Layer {
+    pub inputs: usize,
+    pub outputs: usize,
+    // outputs rows of inputs each
+    pub weights: Vec<f32>,
+    pub biases: Vec<f32>,
+    pub activation: Activation,
+}
+
+impl Layer {
+    pub fn forward(&self, input: &[f32]) -> Vec<f32> {
+        (0..self.outputs)
+            .map(|o| {
+                let row = &self.weights[o * self.inputs..(o + 1) * self.inputs];
+                let sum: f32 = row.iter().zip(input.iter()).map(|(w, x)| w * x).sum();
+                self.activation.apply(sum + self.biases[o])
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone)]
+pub struct Mlp {
+    pub layers: Vec<Layer>,
+}
+
+impl Mlp {
+    pub fn input_len(&self) -> usize {
+        self.layers.first().map_or(0, |layer| layer.inputs)
+    }
+
+    pub fn output_len(&self) -> usize {
+        self.layers.last().map_or(0, |layer| layer.outputs)
+    }
+
+    pub fn forward(&self, input: &[f32]) -> Vec<f32> {
+        if input.len() != self.input_len() {
+            panic!("Network takes {} inputs, got {}", self.input_len(), input.len());
+        }
+        let mut values = input.to_vec();
+        for layer in self.layers.iter() {
+            values = layer.forward(&values);
+        }
+        values
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut tokens = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap())
+            .flat_map(str::split_whitespace);
+
+        let mut layers: Vec<Layer> = vec![];
+        while let Some(token) = tokens.next() {
+            if token != "layer" {
+                return Err(format!("Expected \"layer\", got \"{}\"", token));
+            }
+            let mut size = |what: &str| -> Result<usize, String> {
+                let token = tokens.next().ok_or(format!("Missing {} for layer {}", what, layers.len()))?;
+                match token.parse() {
+                    Ok(0) => Err(format!("Layer {} has an {} of 0", layers.len(), what)),
+                    Ok(size) => Ok(size),
+                    Err(_) => Err(format!("Invalid {} \"{}\"", what, token)),
+                }
+            };
+            let inputs = size("input count")?;
+            let outputs = size("output count")?;
+            let activation = match tokens.next() {
+                Some("relu") => Activation::Relu,
+                Some("tanh") => Activation::Tanh,
+                Some("sigmoid") => Activation::Sigmoid,
+                Some("linear") => Activation::Linear,
+                other => return Err(format!("Unknown activation {:?}", other)),
+            };
+            if let Some(previous) = layers.last() {
+                if previous.outputs != inputs {
+                    return Err(format!(
+                        "Layer {} takes {} inputs but the one before gives {}",
+                        layers.len(),
+                        inputs,
+                        previous.outputs,
+                    ));
+                }
+            }
+
+            let mut numbers = |count: usize| -> Result<Vec<f32>, String> {
+                (0..count)
+                    .map(|_| {
+                        let token = tokens.next().ok_or("Weights file ended early".to_string())?;
+                        // NaN and infinity parse fine but would wreck every score
+                        match token.parse::<f32>() {
+                            Ok(number) if number.is_finite() => Ok(number),
+                            _ => Err(format!("Invalid number \"{}\"", token)),
+                        }
+                    })
+                    .collect()
+            };
+            let weights = numbers(inputs * outputs)?;
+            let biases = numbers(outputs)?;
+            layers.push(Layer { inputs, outputs, weights, biases, activation });
+        }
+
+        if layers.is_empty() {
+            return Err("No layers in weights file".to_string());
+        }
+        Ok(Mlp { layers })
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for layer in self.layers.iter() {
+            text += &format!("layer {} {} {}\n", layer.inputs, layer.outputs, layer.activation.name());
+            for o in 0..layer.outputs {
+                text += &join(&layer.weights[o * layer.inputs..(o + 1) * layer.inputs]);
+            }
+            text += &join(&layer.biases);
+        }
+        text
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+        Mlp::from_text(&text)
+    }
+}
+
+fn join(values: &[f32]) -> String {
+    let strings: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    strings.join(" ") + "\n"
+}
+
+// Expects a fresh one for each match, like the simulation runners make, so
+// it can keep the match score from the rounds it sees
+pub struct MlpStrategy {
+    pub mlp: Mlp,
+    match_scores: Vec<i32>,
+    // Scoring plays can decide to stop and discard instead, in which case the
+    // discard's already been picked
+    pending_discard: Option<Card>,
+    // A discard that scored best as a knock
+    pending_knock: Option<Card>,
+}
+
+impl MlpStrategy {
+    pub fn new(mlp: Mlp) -> Result<Self, String> {
+        if mlp.input_len() != FEATURE_LEN + ACTION_LEN || mlp.output_len() != 1 {
+            return Err(format!(
+                "Network should take {} inputs and give 1 output, this one is {} to {}",
+                FEATURE_LEN + ACTION_LEN,
+                mlp.input_len(),
+                mlp.output_len(),
+            ));
+        }
+        Ok(MlpStrategy {
+            mlp,
+            match_scores: vec![],
+            pending_discard: None,
+            pending_knock: None,
+        })
+    }
+
+    // Network's score for each action
+    pub fn score_actions(&self, view: &PlayerView, actions: &[Action]) -> Vec<f32> {
+        let mut input = features::encode(view, &self.match_scores);
+        actions
+            .iter()
+            .map(|action| {
+                input.truncate(FEATURE_LEN);
+                input.extend(features::encode_action(action, view));
+                self.mlp.forward(&input)[0]
+            })
+            .collect()
+    }
+
+    fn best_action(&self, view: &PlayerView, actions: Vec<Action>) -> Option<Action> {
+        let scores = self.score_actions(view, &actions);
+        actions
+            .into_iter()
+            .zip(scores)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(action, _)| action)
+    }
+}
+
+impl Strategy for MlpStrategy {
+    fn choose_draw(&mut self, view: &PlayerView) -> Draw {
+        self.pending_discard = None;
+        let draws = view.legal_draws().into_iter().map(Action::Draw).collect();
+        match self.best_action(view, draws) {
+            Some(Action::Draw(draw)) => draw,
+            _ => panic!("Invalid game state: nothing to draw"),
+        }
+    }
+
+    fn choose_play(&mut self, view: &PlayerView) -> Option<Play> {
+        if view.legal_plays().is_empty() {
+            return None;
+        }
+        // Every play plus every discard
+        match self.best_action(view, view.legal_actions()) {
+            Some(Action::Play(play)) => Some(*play),
+            Some(Action::Discard(card)) => {
+                self.pending_discard = Some(card);
+                None
+            },
+            _ => None,
+        }
+    }
+
+    fn choose_discard(&mut self, view: &PlayerView) -> Card {
+        self.pending_knock = None;
+        if let Some(card) = self.pending_discard.take() {
+            if view.hand().contains(&card) {
+                return card;
+            }
+        }
+        // Knocks too, in Gin
+        let discards = view
+            .legal_actions()
+            .into_iter()
+            .filter(|action| matches!(action, Action::Discard(_) | Action::Knock(_)))
+            .collect();
+        match self.best_action(view, discards) {
+            Some(Action::Discard(card)) => card,
+            Some(Action::Knock(card)) => {
+                self.pending_knock = Some(card);
+                card
+            },
+            _ => panic!("Invalid game state: nothing in hand to discard"),
+        }
+    }
+
+    fn choose_knock(&mut self, _view: &PlayerView, card: &Card) -> bool {
+        self.pending_knock.take() == Some(*card)
+    }
+
+    fn observe(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::RoundStarted { hand_sizes, .. } => {
+                self.match_scores.resize(hand_sizes.len(), 0);
+            },
+            GameEvent::RoundOver { scores, .. } => {
+                for (total, score) in self.match_scores.iter_mut().zip(scores.iter()) {
+                    *total += score;
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameState;
+    use crate::rules::RuleSet;
+
+    const SMALL: &str = "
+        # two inputs, one hidden layer
+        layer 2 3 relu
+        1 0
+        0 1
+        -1 0.5
+        0 0 0.25
+        layer 3 1 linear
+        1 -1 2
+        0.5
+    ";
+
+    #[test]
+    fn text_round_trips() {
+        let mlp = Mlp::from_text(SMALL).unwrap();
+        assert_eq!(mlp.input_len(), 2);
+        assert_eq!(mlp.output_len(), 1);
+        assert_eq!(mlp.forward(&[1.0, 2.0]), vec![1.0 - 2.0 + 2.0 * 0.25 + 0.5]);
+
+        let again = Mlp::from_text(&mlp.to_text()).unwrap();
+        assert_eq!(again.to_text(), mlp.to_text());
+        assert_eq!(again.forward(&[-3.0, 0.5]), mlp.forward(&[-3.0, 0.5]));
+        assert_eq!(again.layers[0].activation, Activation::Relu);
+    }
+
+    #[test]
+    fn zero_sized_layers_are_rejected() {
+        assert!(Mlp::from_text("layer 0 1 linear\n0").is_err());
+        assert!(Mlp::from_text("layer 1 0 linear\n").is_err());
+    }
+
+    #[test]
+    fn non_finite_weights_are_rejected() {
+        assert!(Mlp::from_text("layer 1 1 linear\nNaN\n0").is_err());
+        assert!(Mlp::from_text("layer 1 1 linear\n1\ninf").is_err());
+    }
+
+    #[test]
+    fn mismatched_layers_are_rejected() {
+        assert!(Mlp::from_text("layer 1 2 relu\n1\n1\n0 0\nlayer 3 1 linear\n1 1 1\n0").is_err());
+        assert!(Mlp::from_text("layer 2 1 linear\n1").is_err());
+        assert!(Mlp::from_text("").is_err());
+    }
+
+    #[test]
+    fn nan_scores_do_not_panic() {
+        let inputs = FEATURE_LEN + ACTION_LEN;
+        let mut weights = vec![0.0; inputs];
+        weights[0] = f32::NAN;
+        let layer = Layer { inputs, outputs: 1, weights, biases: vec![0.0], activation: Activation::Linear };
+        let mut strategy = MlpStrategy::new(Mlp { layers: vec![layer] }).unwrap();
+
+        let state = GameState::deal(2, 1, &RuleSet::default(), 0).unwrap();
+        strategy.choose_draw(&state.view(0));
+    }
+}
@@ -0,0 +1,296 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::card::{Card, CardMultiset};
+use crate::deck::{Deck, DeckCard};
+use crate::game::{self, Action, Draw, GameState, PlayerView, Strategy};
+use crate::greedy::GreedyStrategy;
+use crate::opponent::OpponentModel;
+use crate::score::Play;
+
+// Flat Monte Carlo with determinization.
+//
+// The bot can't see anyone else's hand or the stock, so for every decision it
+// makes up a bunch of "worlds" that fit what it does know: its own hand, the
+// table, the discard pile, how many cards everyone holds, and what they're known
+// to have picked up. Then it tries every option in every world, plays the rest
+// of the round out greedily, and goes with whatever came out best on average.
+// Every option gets the same worlds so luck of the sample doesn't favour one.
+
+#[derive(Clone, Copy)]
+pub struct MonteCarloConfig {
+    // Worlds sampled per decision
+    pub samples: usize,
+    // Rollouts that go on longer than this just get scored where they are
+    pub max_rollout_turns: usize,
+}
+
+impl Default for MonteCarloConfig {
+    fn default() -> Self {
+        MonteCarloConfig {
+            samples: 30,
+            max_rollout_turns: 100,
+        }
+    }
+}
+
+pub struct MonteCarloBot {
+    pub config: MonteCarloConfig,
+    rng: StdRng,
+    // A discard that came out best as a knock
+    pending_knock: Option<Card>,
+}
+
+impl MonteCarloBot {
+    pub fn new(config: MonteCarloConfig, seed: u64) -> Self {
+        MonteCarloBot {
+            config,
+            rng: StdRng::seed_from_u64(seed),
+            pending_knock: None,
+        }
+    }
+
+    fn sample_worlds(&mut self, view: &PlayerView) -> Vec<GameState> {
+        let model = view.opponent_model();
+        (0..self.config.samples)
+            .map(|_| determinize(view, &model, &mut self.rng))
+            .collect()
+    }
+
+    // Average result for `player` of doing `action` in each world and playing
+    // on from there
+    fn evaluate<F: Fn(&mut GameState)>(&self, player: usize, worlds: &[GameState], action: F) -> f64 {
+        let total: f64 = worlds
+            .iter()
+            .map(|world| {
+                let mut world = world.clone();
+                action(&mut world);
+                let scores = rollout(&mut world, &mut GreedyStrategy, self.config.max_rollout_turns);
+                margin(&scores, player)
+            })
+            .sum();
+        total / worlds.len() as f64
+    }
+}
+
+impl Strategy for MonteCarloBot {
+    fn choose_draw(&mut self, view: &PlayerView) -> Draw {
+        let worlds = self.sample_worlds(view);
+        let draws = view.legal_draws();
+        let values: Vec<f64> = draws
+            .iter()
+            .map(|draw| self.evaluate(view.player, &worlds, |world| world.apply_draw(draw)))
+            .collect();
+        draws.into_iter().nth(best_index(&values)).unwrap()
+    }
+
+    fn choose_play(&mut self, view: &PlayerView) -> Option<Play> {
+        let plays = view.legal_plays();
+        if plays.is_empty() {
+            return None;
+        }
+
+        let worlds = self.sample_worlds(view);
+        let mut options: Vec<Option<Play>> = plays.into_iter().map(Some).collect();
+        options.push(None);
+        let values: Vec<f64> = options
+            .iter()
+            .map(|option| {
+                self.evaluate(view.player, &worlds, |world| match option {
+                    Some(play) => world.apply_play(play),
+                    // Holding off means going straight to the discard
+                    None => game::finish_turn(world, &mut GreedyStrategy),
+                })
+            })
+            .collect();
+        options.into_iter().nth(best_index(&values)).unwrap()
+    }
+
+    // Knocking (in Gin) gets weighed up along with plain discards
+    fn choose_discard(&mut self, view: &PlayerView) -> Card {
+        let worlds = self.sample_worlds(view);
+        let options: Vec<Action> = view
+            .legal_actions()
+            .into_iter()
+            .filter(|action| matches!(action, Action::Discard(_) | Action::Knock(_)))
+            .collect();
+        let values: Vec<f64> = options
+            .iter()
+            .map(|option| self.evaluate(view.player, &worlds, |world| world.apply_action(option)))
+            .collect();
+        match options.into_iter().nth(best_index(&values)) {
+            Some(Action::Knock(card)) => {
+                self.pending_knock = Some(card);
+                card
+            },
+            Some(Action::Discard(card)) => {
+                self.pending_knock = None;
+                card
+            },
+            _ => panic!("Invalid game state: nothing in hand to discard"),
+        }
+    }
+
+    fn choose_knock(&mut self, _view: &PlayerView, card: &Card) -> bool {
+        self.pending_knock.take() == Some(*card)
+    }
+}
+
+// A full state that fits what the observer can see, with the rest filled in
+// at random: other hands get the cards they're known to hold plus random
+// unknown cards, and whatever's left becomes the stock
+pub fn determinize<R: Rng>(view: &PlayerView, model: &OpponentModel, rng: &mut R) -> GameState {
+    let unknown = model.unknown_cards(view.hand());
+    let mut unknown: Vec<DeckCard> = unknown
+        .as_ordered_list()
+        .into_iter()
+        .map(DeckCard::Card)
+        .chain(std::iter::repeat_n(DeckCard::Joker, unknown.num_jokers()))
+        .collect();
+    unknown.shuffle(rng);
+
+    let hand_sizes = view.hand_sizes();
+    let mut hands = vec![CardMultiset::new(); view.num_players()];
+    for player in 0..view.num_players() {
+        if player == view.player {
+            continue;
+        }
+        let mut hand = model.players[player].known_cards.clone();
+        while hand.len() < hand_sizes[player] {
+            match unknown.pop() {
+                Some(DeckCard::Card(card)) => hand.add(&card),
+                Some(DeckCard::Joker) => hand.add_joker(),
+                None => break,
+            }
+        }
+        hands[player] = hand;
+    }
+
+    unknown.truncate(view.stock_size());
+    view.fill_in(hands, Deck::from_cards(unknown), rng.gen())
+}
+
+// Plays on with the same strategy for everyone, from wherever the state is
+// (mid-turn is fine)
+pub fn rollout<S: Strategy>(state: &mut GameState, policy: &mut S, max_turns: usize) -> Vec<i32> {
+    let last_turn = state.turns + max_turns;
+    while !state.is_over() && state.turns < last_turn {
+        let player = state.current_player;
+        if !state.has_drawn {
+            let draw = policy.choose_draw(&state.view(player));
+            state.apply_draw(&draw);
+        } else if let Some(play) = policy.choose_play(&state.view(player)) {
+            state.apply_play(&play);
+        } else {
+            game::finish_turn(state, policy);
+        }
+    }
+    state.scores()
+}
+
+// Own score against the average of everyone else's
+pub fn margin(scores: &[i32], seat: usize) -> f64 {
+    let others: i32 = scores.iter().enumerate().filter(|(i, _)| *i != seat).map(|(_, s)| s).sum();
+    scores[seat] as f64 - others as f64 / (scores.len() - 1) as f64
+}
+
+fn best_index(values: &[f64]) -> usize {
+    let mut best = 0;
+    for (i, value) in values.iter().enumerate() {
+        if *value > values[best] {
+            best = i;
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::RuleSet;
+
+    // Player 1 picks up the upcard and throws something else, so it's now
+    // player 0's turn and they know player 1 is holding the upcard
+    fn after_a_pickup(seed: u64) -> (GameState, Card) {
+        let mut state = GameState::deal(2, 0, &RuleSet::default(), seed).unwrap();
+        assert_eq!(state.current_player, 1);
+        let upcard = *state.discard_pile.last().unwrap();
+        state.apply_draw(&Draw::TopDiscard);
+        let discard = state.hands[1].as_ordered_list().into_iter().find(|card| *card != upcard).unwrap();
+        state.apply_discard(&discard);
+        (state, upcard)
+    }
+
+    #[test]
+    fn worlds_only_move_around_cards_the_observer_cant_see() {
+        for seed in 0..5 {
+            let (state, upcard) = after_a_pickup(seed);
+            let view = state.view(0);
+            let model = view.opponent_model();
+            let mut rng = StdRng::seed_from_u64(seed);
+
+            for _ in 0..20 {
+                let world = determinize(&view, &model, &mut rng);
+                assert!(world.hands[0] == state.hands[0]);
+                assert!(world.discard_pile == state.discard_pile);
+                assert_eq!(world.hands[1].len(), state.hands[1].len());
+                assert_eq!(world.stock.len(), state.stock.len());
+                assert!(world.hands[1].contains(&upcard));
+
+                // Still exactly one deck's worth, so nothing the observer
+                // can see turned up again somewhere hidden
+                let mut everything = world.hands[0].clone();
+                for card in world.hands[1].as_ordered_list().iter().chain(world.discard_pile.iter()) {
+                    everything.add(card);
+                }
+                for card in world.stock.cards().iter() {
+                    match card {
+                        DeckCard::Card(card) => everything.add(card),
+                        DeckCard::Joker => everything.add_joker(),
+                    }
+                }
+                let deck: Vec<Card> = Deck::new(1, 0).cards().iter().map(|card| match card {
+                    DeckCard::Card(card) => *card,
+                    DeckCard::Joker => panic!("No jokers in this deck"),
+                }).collect();
+                assert!(everything == CardMultiset::from_vec(&deck));
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_same_game() {
+        let config = MonteCarloConfig { samples: 4, max_rollout_turns: 20 };
+        let play = |seed: u64| {
+            let mut state = GameState::deal(2, 0, &RuleSet::default(), 11).unwrap();
+            state.turn_cap = 12;
+            let mut bots: Vec<Box<dyn Strategy>> = vec![
+                Box::new(MonteCarloBot::new(config, seed)),
+                Box::new(MonteCarloBot::new(config, seed + 1)),
+            ];
+            // play_round panics on anything illegal
+            let scores = game::play_round(&mut state, &mut bots);
+            (scores, state.history.len())
+        };
+        assert_eq!(play(3), play(3));
+    }
+
+    #[test]
+    fn draws_and_discards_are_legal() {
+        let (state, _) = after_a_pickup(2);
+        let view = state.view(0);
+        let mut bot = MonteCarloBot::new(MonteCarloConfig { samples: 4, max_rollout_turns: 20 }, 0);
+        let draw = bot.choose_draw(&view);
+        assert!(view.legal_draws().iter().any(|legal| match (legal, &draw) {
+            (Draw::Stock, Draw::Stock) | (Draw::TopDiscard, Draw::TopDiscard) => true,
+            (Draw::Dig(a), Draw::Dig(b)) => game::same_play(a, b),
+            _ => false,
+        }));
+
+        let mut state = state.clone();
+        state.apply_draw(&Draw::Stock);
+        let card = bot.choose_discard(&state.view(0));
+        assert!(state.hands[0].contains(&card));
+    }
+}
@@ -0,0 +1,281 @@
+use crate::card::{Card, CardMultiset, CardSet};
+use crate::deck::{Deck, DeckCard};
+use crate::game::GameEvent;
+use crate::score::PlayedCards;
+
+// Keeping track of what the other players are holding, the way a good player
+// does at the table.
+//
+// Anything somebody picks up off the discard pile is known to be in their hand
+// until they play it or throw it away. Cards they throw away, or leave sitting
+// on top of the pile when they draw from the stock, they probably don't want,
+// which makes cards that would go with those less likely to be in their hand too.
+//
+// PlayerView::opponent_model works one out from the round's history, so
+// strategies don't need to keep their own.
+
+// How much less likely a card is to be held when it would go with something
+// the player doesn't want
+const UNWANTED_NEIGHBOUR_WEIGHT: f64 = 0.5;
+
+#[derive(Clone, Default)]
+pub struct OpponentInfo {
+    pub hand_size: usize,
+    // Picked up from the discard pile and not played or discarded since. More
+    // than one deck means there can be two of the same card
+    pub known_cards: CardMultiset,
+    // Discarded, or passed up on top of the discard pile
+    pub unwanted: CardSet,
+}
+
+#[derive(Clone)]
+pub struct OpponentModel {
+    // Whose point of view this is
+    pub observer: usize,
+    // Indexed by player. The observer's own entry gets kept up to date too
+    // but there's nothing to guess about it
+    pub players: Vec<OpponentInfo>,
+    pub discard_pile: Vec<Card>,
+    pub played_cards: PlayedCards,
+    // Every card the round was dealt from, jokers included
+    pub deck: CardMultiset,
+}
+
+impl OpponentModel {
+    pub fn new(num_players: usize, observer: usize) -> Self {
+        OpponentModel {
+            observer,
+            players: vec![OpponentInfo::default(); num_players],
+            discard_pile: vec![],
+            played_cards: PlayedCards::default(),
+            deck: all_cards(1, 0),
+        }
+    }
+
+    pub fn observe(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::RoundStarted { hand_sizes, upcard, num_decks, jokers_per_deck, .. } => {
+                for (info, hand_size) in self.players.iter_mut().zip(hand_sizes.iter()) {
+                    *info = OpponentInfo { hand_size: *hand_size, ..OpponentInfo::default() };
+                }
+                self.discard_pile = vec![*upcard];
+                self.played_cards = PlayedCards::default();
+                self.deck = all_cards(*num_decks, *jokers_per_deck);
+            },
+            GameEvent::DrewFromStock { player } => {
+                let info = &mut self.players[*player];
+                info.hand_size += 1;
+                if let Some(top) = self.discard_pile.last() {
+                    if !info.unwanted.contains(top) {
+                        info.unwanted.add(top);
+                    }
+                }
+            },
+            GameEvent::TookDiscards { player, cards } => {
+                let info = &mut self.players[*player];
+                info.hand_size += cards.len();
+                for card in cards.iter() {
+                    info.known_cards.add(card);
+                    if info.unwanted.contains(card) {
+                        info.unwanted.remove(card);
+                    }
+                }
+                let new_len = self.discard_pile.len() - cards.len();
+                self.discard_pile.truncate(new_len);
+            },
+            GameEvent::Played { player, play } => {
+                let info = &mut self.players[*player];
+                let num_jokers = play.wilds.iter().filter(|w| w.wild.is_none()).count();
+                info.hand_size = info.hand_size.saturating_sub(play.cards_used.as_ordered_list().len() + num_jokers);
+                for card in play.cards_used.as_ordered_list().iter() {
+                    if info.known_cards.contains(card) {
+                        info.known_cards.remove(card);
+                    }
+                }
+                self.played_cards.add_play(play);
+            },
+            GameEvent::Discarded { player, card } | GameEvent::Knocked { player, card } => {
+                let info = &mut self.players[*player];
+                info.hand_size = info.hand_size.saturating_sub(1);
+                if info.known_cards.contains(card) {
+                    info.known_cards.remove(card);
+                }
+                if !info.unwanted.contains(card) {
+                    info.unwanted.add(card);
+                }
+                self.discard_pile.push(*card);
+            },
+            // The joker went under the stock, where it's as unknown as ever
+            GameEvent::DiscardedJoker { player } => {
+                let info = &mut self.players[*player];
+                info.hand_size = info.hand_size.saturating_sub(1);
+            },
+            GameEvent::StockReshuffled { .. } => {
+                let top = self.discard_pile.pop();
+                self.discard_pile = top.into_iter().collect();
+            },
+            GameEvent::RoundOver { .. } => {},
+        }
+    }
+
+    // Cards the observer can't see and doesn't know the whereabouts of: the
+    // stock plus the unknown parts of everyone else's hands. Jokers included
+    pub fn unknown_cards(&self, hand: &CardMultiset) -> CardMultiset {
+        let mut unknown = self.deck.clone();
+        let mut seen = hand.clone();
+        for card in self.discard_pile.iter() {
+            seen.add(card);
+        }
+        let played = &self.played_cards;
+        for card in played.straight_flush_played.as_ordered_list().iter() {
+            seen.add(card);
+        }
+        for card in played.multiple_played.as_ordered_list().iter() {
+            seen.add(card);
+        }
+        for wild in played.straight_flush_wilds.iter().chain(played.multiple_wilds.iter()) {
+            match wild.wild {
+                Some(card) => seen.add(&card),
+                None => seen.add_joker(),
+            }
+        }
+        for (player, info) in self.players.iter().enumerate() {
+            if player != self.observer {
+                for card in info.known_cards.as_ordered_list().iter() {
+                    seen.add(card);
+                }
+            }
+        }
+
+        for card in seen.as_ordered_list().iter() {
+            if unknown.contains(card) {
+                unknown.remove(card);
+            }
+        }
+        for _ in 0..seen.num_jokers().min(unknown.num_jokers()) {
+            unknown.remove_joker();
+        }
+        unknown
+    }
+
+    // Chance that each card the observer can't see is in the player's hand.
+    // Known cards are certain. The rest of their hand is spread over the
+    // unknown cards, less so over ones that go with what they don't want. A
+    // card with more than one unknown copy is that much more likely
+    pub fn card_probabilities(&self, player: usize, hand: &CardMultiset) -> Vec<(Card, f64)> {
+        let info = &self.players[player];
+        let known = info.known_cards.distinct();
+        let mut probabilities: Vec<(Card, f64)> =
+            known.as_ordered_list().iter().map(|card| (*card, 1.0)).collect();
+
+        let unknown = self.unknown_cards(hand);
+        let unknown_slots = info.hand_size.saturating_sub(info.known_cards.len());
+        if unknown.is_empty() || unknown_slots == 0 {
+            return probabilities;
+        }
+
+        let weights: Vec<(Card, f64)> = unknown
+            .distinct()
+            .as_ordered_list()
+            .into_iter()
+            .filter(|card| !known.contains(card))
+            .map(|card| {
+                let weight = if goes_with_any(&card, &info.unwanted) { UNWANTED_NEIGHBOUR_WEIGHT } else { 1.0 };
+                (card, weight * unknown.count(&card) as f64)
+            })
+            .collect();
+        let total_weight = weights.iter().map(|(_, weight)| weight).sum::<f64>() + unknown.num_jokers() as f64;
+        for (card, weight) in weights.into_iter() {
+            let probability = (unknown_slots as f64 * weight / total_weight).min(1.0);
+            probabilities.push((card, probability));
+        }
+        probabilities
+    }
+
+    pub fn probability(&self, player: usize, card: &Card, hand: &CardMultiset) -> f64 {
+        self.card_probabilities(player, hand)
+            .iter()
+            .find(|(c, _)| c == card)
+            .map_or(0.0, |(_, probability)| *probability)
+    }
+}
+
+fn all_cards(num_decks: usize, jokers_per_deck: usize) -> CardMultiset {
+    let mut cards = CardMultiset::new();
+    for card in Deck::new(num_decks, jokers_per_deck).cards().iter() {
+        match card {
+            DeckCard::Card(card) => cards.add(card),
+            DeckCard::Joker => cards.add_joker(),
+        }
+    }
+    cards
+}
+
+// Same value, or next to it in the same suit
+fn goes_with_any(card: &Card, cards: &CardSet) -> bool {
+    cards.as_ordered_list().iter().any(|other| {
+        let distance = (card.value.index() as i32 - other.value.index() as i32).abs();
+        card.value == other.value || (card.suit == other.suit && distance == 1)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Draw, GameState};
+    use crate::rules::RuleSet;
+
+    #[test]
+    fn cards_taken_from_the_discard_pile_are_known() {
+        let mut state = GameState::deal(2, 1, &RuleSet::default(), 4).unwrap();
+        let upcard = state.discard_pile[0];
+        state.apply_draw(&Draw::TopDiscard);
+
+        let model = state.view(1).opponent_model();
+        assert!(model.players[0].known_cards.contains(&upcard));
+        assert_eq!(model.players[0].hand_size, 14);
+        assert!(model.probability(0, &upcard, state.view(1).hand()) == 1.0);
+        assert!(!model.unknown_cards(state.view(1).hand()).contains(&upcard));
+    }
+
+    #[test]
+    fn passing_up_the_top_discard_marks_it_unwanted() {
+        let mut state = GameState::deal(2, 1, &RuleSet::default(), 4).unwrap();
+        let upcard = state.discard_pile[0];
+        state.apply_draw(&Draw::Stock);
+
+        let model = state.view(1).opponent_model();
+        assert!(model.players[0].unwanted.contains(&upcard));
+        assert!(model.players[0].known_cards.is_empty());
+    }
+
+    #[test]
+    fn unknown_cards_cover_everything_out_of_sight() {
+        let state = GameState::deal(2, 1, &RuleSet::default(), 4).unwrap();
+        let view = state.view(1);
+        let unknown = view.opponent_model().unknown_cards(view.hand());
+        // 52 less 13 in hand and the upcard
+        assert_eq!(unknown.len(), 38);
+
+        let probabilities = view.opponent_model().card_probabilities(0, view.hand());
+        let expected_cards: f64 = probabilities.iter().map(|(_, probability)| probability).sum();
+        assert!((expected_cards - 13.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn a_second_deck_leaves_copies_unknown() {
+        let rules = RuleSet { num_decks: Some(2), jokers_per_deck: 1, ..RuleSet::default() };
+        let state = GameState::deal(2, 1, &rules, 4).unwrap();
+        let view = state.view(1);
+        let unknown = view.opponent_model().unknown_cards(view.hand());
+        assert_eq!(unknown.len(), 2 * 53 - 13 - 1);
+        assert_eq!(unknown.num_jokers() + view.hand().num_jokers(), 2);
+    }
+
+    #[test]
+    fn hand_sizes_never_go_below_zero() {
+        let mut model = OpponentModel::new(2, 1);
+        model.observe(&GameEvent::Discarded { player: 0, card: "5:H".parse().unwrap() });
+        assert_eq!(model.players[0].hand_size, 0);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::analysis::{self, HandAnalysis};
+use crate::card::{Card, CardMultiset, CardSet};
+use crate::rules::RuleSet;
+use crate::score::{self, Play, PlayedCards};
+
+// How deep to dig into the discard pile.
+//
+// Taking just the top card is always allowed. Going deeper means taking every
+// card above the one you want too, and the one you dug for has to be played
+// right away. So each depth is a trade: the points from that play, against a
+// pile of extra cards that count against you if the round ends before you get
+// rid of them (though they might set up melds of their own).
+
+pub struct PickupOption {
+    // Number of cards taken off the top of the pile
+    pub depth: usize,
+    // Best scoring play using the deepest card taken. Only depth 1 can do
+    // without one
+    pub play: Option<Play>,
+    pub points_gained: u32,
+    // Cards taken that the play doesn't use, so they stay in hand
+    pub extra_cards: CardSet,
+    // What the extra cards cost if the round ends with them still in hand
+    pub deadwood_risk: u32,
+    // Outs the hand has after the pickup that it didn't have before
+    pub new_outs: CardSet,
+    // The hand after the pickup (and play), with what's still on the pile and table
+    pub analysis: HandAnalysis,
+}
+
+impl PickupOption {
+    // Points now minus what it could cost later
+    pub fn net_points(&self) -> i32 {
+        self.points_gained as i32 - self.deadwood_risk as i32
+    }
+}
+
+// One option per depth that can actually be taken, shallowest first
+pub fn evaluate_pickups(
+    hand: &CardSet,
+    discard_pile: &[Card],
+    played_cards: &PlayedCards,
+    rules: &RuleSet,
+) -> Vec<PickupOption> {
+    if discard_pile.is_empty() {
+        return vec![];
+    }
+
+    let before = analysis::analyze(hand, discard_pile, played_cards, rules);
+    let plays = score::all_possible_plays(hand, discard_pile, played_cards, rules);
+    let max_depth = if rules.can_dig_discard_pile { discard_pile.len() } else { 1 };
+
+    let mut options = vec![];
+    for depth in 1..=max_depth {
+        let best_play = plays
+            .iter()
+            .filter(|play| play.cards_acquired.len() == depth)
+            .max_by_key(|play| play.points(rules));
+
+        if best_play.is_none() && depth > 1 {
+            continue;
+        }
+
+        let taken = &discard_pile[discard_pile.len() - depth..];
+        let left_on_pile = &discard_pile[..discard_pile.len() - depth];
+        let mut new_hand = *hand;
+        let mut new_played = played_cards.clone();
+        let mut extra_cards = CardSet::new();
+        let mut points_gained = 0;
+
+        for card in taken.iter() {
+            new_hand.add(card);
+        }
+        if let Some(play) = best_play {
+            for card in play.cards_used.as_ordered_list().iter() {
+                new_hand.remove(card);
+            }
+            let num_jokers = play.wilds.iter().filter(|w| w.wild.is_none()).count();
+            for _ in 0..num_jokers {
+                new_hand.remove_joker();
+            }
+            new_played.add_play(play);
+            points_gained = play.points(rules);
+        }
+        for card in taken.iter() {
+            if new_hand.contains(card) {
+                extra_cards.add(card);
+            }
+        }
+
+        let after = analysis::analyze(&new_hand, left_on_pile, &new_played, rules);
+        let mut new_outs = CardSet::new();
+        for card in after.all_outs.as_ordered_list().iter() {
+            if !before.all_outs.contains(card) {
+                new_outs.add(card);
+            }
+        }
+
+        options.push(PickupOption {
+            depth,
+            play: best_play.cloned(),
+            points_gained,
+            deadwood_risk: score::hand_points(&CardMultiset::from(&extra_cards), rules),
+            extra_cards,
+            new_outs,
+            analysis: after,
+        });
+    }
+    options
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card_list(s: &str) -> Vec<Card> {
+        s.split_whitespace().map(|c| c.parse().unwrap()).collect()
+    }
+
+    fn cards(s: &str) -> CardSet {
+        CardSet::from_vec(&card_list(s))
+    }
+
+    // King on top, then a nine that makes a set, then a seven that makes a run
+    fn three_deep() -> Vec<PickupOption> {
+        evaluate_pickups(
+            &cards("5:H 6:H 2:C 9:C 9:D"),
+            &card_list("7:H 9:S K:D"),
+            &PlayedCards::default(),
+            &RuleSet::default(),
+        )
+    }
+
+    #[test]
+    fn every_reachable_depth_gets_an_option() {
+        let options = three_deep();
+        let depths: Vec<usize> = options.iter().map(|option| option.depth).collect();
+        assert_eq!(depths, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn top_card_alone_needs_no_play() {
+        let top = &three_deep()[0];
+        assert!(top.play.is_none());
+        assert_eq!(top.points_gained, 0);
+        assert!(top.extra_cards == cards("K:D"));
+        assert_eq!(top.deadwood_risk, 10);
+        assert!(top.new_outs.is_empty());
+        assert_eq!(top.net_points(), -10);
+    }
+
+    #[test]
+    fn digging_plays_the_deepest_card() {
+        let options = three_deep();
+
+        let nines = &options[1];
+        assert!(nines.play.as_ref().unwrap().cards_used == cards("9:C 9:D 9:S"));
+        assert_eq!(nines.points_gained, 15);
+        assert!(nines.extra_cards == cards("K:D"));
+        assert_eq!(nines.deadwood_risk, 10);
+        // The last nine was already an out for the pair
+        assert!(nines.new_outs.is_empty());
+
+        let run = &options[2];
+        assert!(run.play.as_ref().unwrap().cards_used == cards("5:H 6:H 7:H"));
+        assert_eq!(run.points_gained, 15);
+        assert!(run.extra_cards == cards("9:S K:D"));
+        assert_eq!(run.deadwood_risk, 15);
+        // The eight lays off on the run now that it's down
+        assert!(run.new_outs == cards("8:H"));
+        assert_eq!(run.net_points(), 0);
+    }
+
+    #[test]
+    fn only_the_top_card_without_digging() {
+        let rules = RuleSet { can_dig_discard_pile: false, ..RuleSet::default() };
+        let options = evaluate_pickups(
+            &cards("5:H 6:H 2:C 9:C 9:D"),
+            &card_list("7:H 9:S K:D"),
+            &PlayedCards::default(),
+            &rules,
+        );
+        assert_eq!(options.len(), 1);
+        assert_eq!(options[0].depth, 1);
+        assert!(options[0].extra_cards == cards("K:D"));
+    }
+}
@@ -0,0 +1,624 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::card::{self, CardSuit, CardValue, JOKER_STR, NUM_CARD_VALUES};
+use crate::export::{self, GameRow, TurnRow};
+use crate::game::Strategy;
+use crate::game_match::MatchConfig;
+use crate::gin;
+use crate::rules::{AceRule, GameKind, RuleSet, StockExhaustion};
+use crate::score::{self, AceStatus, PlayKind, WildUse};
+use crate::simulation;
+
+// Python side of things (built with maturin, see pyproject.toml)
+//
+// Cards are written the same way they print, e.g. Card("10:H"), so
+//     hand = CardSet([Card("A:S"), Card("2:S"), Card("3:S")])
+//     plays = all_possible_plays(hand, [], PlayedCards(CardSet(), CardSet()))
+// and each Play has a to_dict() for dumping into pandas.
+//
+// Simulations come back as plain dicts and lists, with the same rows the
+// export module writes:
+//     result = simulate_matches(["greedy", "monte_carlo"], 100, seed=1, turns=True)
+//     games = pandas.DataFrame(result["games"])
+
+#[pyclass(name = "Card", frozen, eq, from_py_object)]
+#[derive(Clone, PartialEq)]
+pub struct PyCard {
+    inner: card::Card,
+}
+
+#[pymethods]
+impl PyCard {
+    #[new]
+    fn new(s: &str) -> PyResult<Self> {
+        let inner = s.parse().map_err(PyValueError::new_err)?;
+        Ok(PyCard { inner })
+    }
+
+    // Ace is 0, King is 12 (same as CardValue::index)
+    #[getter]
+    fn value(&self) -> usize {
+        self.inner.value.index()
+    }
+
+    #[getter]
+    fn suit(&self) -> String {
+        format!("{:?}", self.inner.suit)
+    }
+
+    fn __hash__(&self) -> usize {
+        self.inner.suit.index() * NUM_CARD_VALUES + self.inner.value.index()
+    }
+
+    fn __str__(&self) -> String {
+        self.inner.to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Card(\"{}\")", self.inner)
+    }
+}
+
+#[pyclass(name = "CardSet", from_py_object)]
+#[derive(Clone)]
+pub struct PyCardSet {
+    inner: card::CardSet,
+}
+
+#[pymethods]
+impl PyCardSet {
+    #[new]
+    #[pyo3(signature = (cards = vec![]))]
+    fn new(cards: Vec<PyCard>) -> PyResult<Self> {
+        let mut set = PyCardSet { inner: card::CardSet::new() };
+        for card in cards.iter() {
+            set.add(card)?;
+        }
+        Ok(set)
+    }
+
+    fn add(&mut self, card: &PyCard) -> PyResult<()> {
+        if self.inner.contains(&card.inner) {
+            let msg = format!("Set already contains {}", card.inner);
+            return Err(PyValueError::new_err(msg));
+        }
+        self.inner.add(&card.inner);
+        Ok(())
+    }
+
+    fn remove(&mut self, card: &PyCard) -> PyResult<()> {
+        if !self.inner.contains(&card.inner) {
+            let msg = format!("Set doesn't contain {}", card.inner);
+            return Err(PyValueError::new_err(msg));
+        }
+        self.inner.remove(&card.inner);
+        Ok(())
+    }
+
+    fn add_joker(&mut self) {
+        self.inner.add_joker();
+    }
+
+    fn remove_joker(&mut self) -> PyResult<()> {
+        if self.inner.num_jokers() == 0 {
+            return Err(PyValueError::new_err("Set doesn't have a joker"));
+        }
+        self.inner.remove_joker();
+        Ok(())
+    }
+
+    #[getter]
+    fn num_jokers(&self) -> usize {
+        self.inner.num_jokers()
+    }
+
+    // Not including jokers
+    fn cards(&self) -> Vec<PyCard> {
+        to_py_cards(&self.inner.as_ordered_list())
+    }
+
+    fn __contains__(&self, card: &PyCard) -> bool {
+        self.inner.contains(&card.inner)
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.as_ordered_list().len() + self.inner.num_jokers()
+    }
+
+    fn __str__(&self) -> String {
+        self.inner.to_string()
+    }
+}
+
+// Wilds on the table are (wild, stands_for) pairs where wild is None for a joker
+type PyWildUse = (Option<PyCard>, PyCard);
+
+#[pyclass(name = "PlayedCards", from_py_object)]
+#[derive(Clone)]
+pub struct PyPlayedCards {
+    #[pyo3(get, set)]
+    straight_flush_played: PyCardSet,
+    #[pyo3(get, set)]
+    multiple_played: PyCardSet,
+    #[pyo3(get, set)]
+    straight_flush_wilds: Vec<PyWildUse>,
+    #[pyo3(get, set)]
+    multiple_wilds: Vec<PyWildUse>,
+}
+
+#[pymethods]
+impl PyPlayedCards {
+    #[new]
+    #[pyo3(signature = (
+        straight_flush_played, 
+        multiple_played, 
+        straight_flush_wilds = vec![], 
+        multiple_wilds = vec![],
+    ))]
+    fn new(
+        straight_flush_played: PyCardSet, 
+        multiple_played: PyCardSet,
+        straight_flush_wilds: Vec<PyWildUse>,
+        multiple_wilds: Vec<PyWildUse>,
+    ) -> Self {
+        PyPlayedCards { straight_flush_played, multiple_played, straight_flush_wilds, multiple_wilds }
+    }
+}
+
+impl PyPlayedCards {
+    fn to_rust(&self) -> score::PlayedCards {
+        let to_wild_uses = |wilds: &Vec<PyWildUse>| -> Vec<WildUse> {
+            wilds.iter()
+                .map(|(wild, stands_for)| WildUse {
+                    wild: wild.as_ref().map(|c| c.inner),
+                    stands_for: stands_for.inner,
+                })
+                .collect()
+        };
+        score::PlayedCards {
+            straight_flush_played: self.straight_flush_played.inner,
+            multiple_played: self.multiple_played.inner,
+            straight_flush_wilds: to_wild_uses(&self.straight_flush_wilds),
+            multiple_wilds: to_wild_uses(&self.multiple_wilds),
+        }
+    }
+}
+
+// Same fields as rules::RuleSet, with game as "FiveHundred" or "Gin", ace_rule
+// as "Low", "High" or "LowOrHigh" and stock_exhaustion as "EndRound",
+// "ReshuffleDiscards" or "DiscardPileOnly". RuleSet() gives the defaults and
+// RuleSet.gin() the Gin ones
+#[pyclass(name = "RuleSet", from_py_object)]
+#[derive(Clone, Default)]
+pub struct PyRuleSet {
+    inner: RuleSet,
+}
+
+#[pymethods]
+impl PyRuleSet {
+    #[new]
+    fn new() -> Self {
+        PyRuleSet::default()
+    }
+
+    #[staticmethod]
+    fn gin() -> Self {
+        PyRuleSet { inner: gin::rules() }
+    }
+
+    #[getter]
+    fn game(&self) -> String {
+        format!("{:?}", self.inner.game)
+    }
+
+    #[setter]
+    fn set_game(&mut self, game: &str) -> PyResult<()> {
+        self.inner.game = match game {
+            "FiveHundred" => GameKind::FiveHundred,
+            "Gin" => GameKind::Gin,
+            _ => return Err(PyValueError::new_err(format!("Invalid game: {}", game))),
+        };
+        Ok(())
+    }
+
+    #[getter]
+    fn min_multiple_size(&self) -> usize {
+        self.inner.min_multiple_size
+    }
+
+    #[setter]
+    fn set_min_multiple_size(&mut self, size: usize) {
+        self.inner.min_multiple_size = size;
+    }
+
+    #[getter]
+    fn min_run_size(&self) -> usize {
+        self.inner.min_run_size
+    }
+
+    #[setter]
+    fn set_min_run_size(&mut self, size: usize) {
+        self.inner.min_run_size = size;
+    }
+
+    #[getter]
+    fn ace_rule(&self) -> String {
+        format!("{:?}", self.inner.ace_rule)
+    }
+
+    #[setter]
+    fn set_ace_rule(&mut self, ace_rule: &str) -> PyResult<()> {
+        self.inner.ace_rule = match ace_rule {
+            "Low" => AceRule::Low,
+            "High" => AceRule::High,
+            "LowOrHigh" => AceRule::LowOrHigh,
+            _ => return Err(PyValueError::new_err(format!("Invalid ace rule: {}", ace_rule))),
+        };
+        Ok(())
+    }
+
+    #[getter]
+    fn allow_wrap_around(&self) -> bool {
+        self.inner.allow_wrap_around
+    }
+
+    #[setter]
+    fn set_allow_wrap_around(&mut self, b: bool) {
+        self.inner.allow_wrap_around = b;
+    }
+
+    #[getter]
+    fn can_dig_discard_pile(&self) -> bool {
+        self.inner.can_dig_discard_pile
+    }
+
+    #[setter]
+    fn set_can_dig_discard_pile(&mut self, b: bool) {
+        self.inner.can_dig_discard_pile = b;
+    }
+
+    #[getter]
+    fn final_discard_to_go_out(&self) -> bool {
+        self.inner.final_discard_to_go_out
+    }
+
+    #[setter]
+    fn set_final_discard_to_go_out(&mut self, b: bool) {
+        self.inner.final_discard_to_go_out = b;
+    }
+
+    // Value index (Ace is 0) of the wild rank, or None
+    #[getter]
+    fn wild_value(&self) -> Option<usize> {
+        self.inner.wild_value.map(|v| v.index())
+    }
+
+    #[setter]
+    fn set_wild_value(&mut self, value: Option<usize>) -> PyResult<()> {
+        if let Some(i) = value {
+            if i >= NUM_CARD_VALUES {
+                return Err(PyValueError::new_err(format!("Invalid card value: {}", i)));
+            }
+        }
+        self.inner.wild_value = value.map(CardValue::from_index);
+        Ok(())
+    }
+
+    #[getter]
+    fn joker_points(&self) -> u32 {
+        self.inner.joker_points
+    }
+
+    #[setter]
+    fn set_joker_points(&mut self, points: u32) {
+        self.inner.joker_points = points;
+    }
+
+    #[getter]
+    fn stock_exhaustion(&self) -> String {
+        format!("{:?}", self.inner.stock_exhaustion)
+    }
+
+    #[setter]
+    fn set_stock_exhaustion(&mut self, stock_exhaustion: &str) -> PyResult<()> {
+        self.inner.stock_exhaustion = match stock_exhaustion {
+            "EndRound" => StockExhaustion::EndRound,
+            "ReshuffleDiscards" => StockExhaustion::ReshuffleDiscards,
+            "DiscardPileOnly" => StockExhaustion::DiscardPileOnly,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "Invalid stock exhaustion rule: {}",
+                    stock_exhaustion,
+                )))
+            },
+        };
+        Ok(())
+    }
+
+    // None goes by the number of players
+    #[getter]
+    fn num_decks(&self) -> Option<usize> {
+        self.inner.num_decks
+    }
+
+    #[setter]
+    fn set_num_decks(&mut self, num_decks: Option<usize>) -> PyResult<()> {
+        if num_decks == Some(0) {
+            return Err(PyValueError::new_err("Need at least one deck"));
+        }
+        self.inner.num_decks = num_decks;
+        Ok(())
+    }
+
+    #[getter]
+    fn jokers_per_deck(&self) -> usize {
+        self.inner.jokers_per_deck
+    }
+
+    #[setter]
+    fn set_jokers_per_deck(&mut self, jokers: usize) {
+        self.inner.jokers_per_deck = jokers;
+    }
+
+    #[getter]
+    fn target_score(&self) -> i32 {
+        self.inner.target_score
+    }
+
+    #[setter]
+    fn set_target_score(&mut self, score: i32) {
+        self.inner.target_score = score;
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.inner)
+    }
+}
+
+#[pyclass(name = "Play", frozen)]
+pub struct PyPlay {
+    // "StraightFlush" or "Multiple"
+    #[pyo3(get)]
+    kind: String,
+    // "High", "Low" or None (for multiples, and straights without an ace)
+    #[pyo3(get)]
+    ace_status: Option<String>,
+    #[pyo3(get)]
+    cards_used: Vec<PyCard>,
+    #[pyo3(get)]
+    cards_acquired: Vec<PyCard>,
+    #[pyo3(get)]
+    wilds: Vec<PyWildUse>,
+}
+
+#[pymethods]
+impl PyPlay {
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let card_strs = |cards: &Vec<PyCard>| -> Vec<String> {
+            cards.iter().map(|c| c.inner.to_string()).collect()
+        };
+        let d = PyDict::new(py);
+        d.set_item("kind", &self.kind)?;
+        d.set_item("ace_status", &self.ace_status)?;
+        d.set_item("cards_used", card_strs(&self.cards_used))?;
+        d.set_item("cards_acquired", card_strs(&self.cards_acquired))?;
+        let wild_strs: Vec<(String, String)> = self.wilds.iter()
+            .map(|(wild, stands_for)| {
+                let wild_str = match wild {
+                    Some(c) => c.inner.to_string(),
+                    None => JOKER_STR.to_string(),
+                };
+                (wild_str, stands_for.inner.to_string())
+            })
+            .collect();
+        d.set_item("wilds", wild_strs)?;
+        Ok(d)
+    }
+
+    fn __repr__(&self) -> String {
+        let cards: Vec<String> = self.cards_used.iter().map(|c| c.inner.to_string()).collect();
+        format!("Play({}, {:?})", self.kind, cards)
+    }
+}
+
+impl PyPlay {
+    fn from_rust(play: score::Play) -> Self {
+        let (kind, ace_status) = match play.kind {
+            PlayKind::StraightFlush { ace_status } => {
+                let ace_status = ace_status.map(|a| match a {
+                    AceStatus::High => "High".to_string(),
+                    AceStatus::Low => "Low".to_string(),
+                });
+                ("StraightFlush".to_string(), ace_status)
+            },
+            PlayKind::Multiple => ("Multiple".to_string(), None),
+        };
+        PyPlay {
+            kind,
+            ace_status,
+            cards_used: to_py_cards(&play.cards_used.as_ordered_list()),
+            cards_acquired: to_py_cards(&play.cards_acquired.as_ordered_list()),
+            wilds: play.wilds.iter()
+                .map(|w| (w.wild.map(|c| PyCard { inner: c }), PyCard { inner: w.stands_for }))
+                .collect(),
+        }
+    }
+}
+
+fn to_py_cards(cards: &[card::Card]) -> Vec<PyCard> {
+    cards.iter().map(|c| PyCard { inner: *c }).collect()
+}
+
+// discard_pile is bottom of the pile first, same as score::all_possible_plays
+#[pyfunction]
+#[pyo3(signature = (hand, discard_pile, played_cards, rules = None))]
+fn all_possible_plays(
+    hand: &PyCardSet,
+    discard_pile: Vec<PyCard>,
+    played_cards: &PyPlayedCards,
+    rules: Option<PyRuleSet>,
+) -> PyResult<Vec<PyPlay>> {
+    let discard_pile: Vec<card::Card> = discard_pile.iter().map(|c| c.inner).collect();
+    let played_cards = played_cards.to_rust();
+
+    // One deck unless the rules say otherwise, there's no telling how many
+    // are playing
+    let rules = rules.unwrap_or_default().inner;
+    let num_decks = rules.num_decks.unwrap_or(1);
+    score::validate_position(&hand.inner, &discard_pile, &played_cards, &rules, num_decks)
+        .map_err(PyValueError::new_err)?;
+
+    let plays = score::all_possible_plays(&hand.inner, &discard_pile, &played_cards, &rules);
+    Ok(plays.into_iter().map(PyPlay::from_rust).collect())
+}
+
+// Every card in the deck, handy for building hands in python without typing them all out
+#[pyfunction]
+fn full_deck() -> Vec<PyCard> {
+    let mut cards = vec![];
+    for suit in CardSuit::iter() {
+        for value in CardValue::iter() {
+            cards.push(PyCard { inner: card::Card { suit, value } });
+        }
+    }
+    cards
+}
+
+// One strategy name per seat (see simulation::STRATEGY_NAMES). Returns a dict
+// with the totals, a summary per seat under "seats" (intervals are 95%), one
+// row per round under "games", and one per turn under "turns" if asked for
+#[pyfunction]
+#[pyo3(signature = (
+    strategies,
+    num_matches,
+    seed = 0,
+    rules = None,
+    max_rounds = None,
+    turns = false,
+))]
+fn simulate_matches<'py>(
+    py: Python<'py>,
+    strategies: Vec<String>,
+    num_matches: usize,
+    seed: u64,
+    rules: Option<PyRuleSet>,
+    max_rounds: Option<usize>,
+    turns: bool,
+) -> PyResult<Bound<'py, PyDict>> {
+    let num_players = strategies.len();
+    if num_players < 2 {
+        return Err(PyValueError::new_err("Need at least 2 strategies, one per seat"));
+    }
+    for name in strategies.iter() {
+        simulation::strategy_by_name(name, 0, 0).map_err(PyValueError::new_err)?;
+    }
+    let rules = rules.unwrap_or_default().inner;
+    let mut config = MatchConfig::default();
+    if let Some(max_rounds) = max_rounds {
+        config.max_rounds = max_rounds;
+    }
+
+    let mut game_rows: Vec<GameRow> = vec![];
+    let mut turn_rows: Vec<TurnRow> = vec![];
+    let report = simulation::simulate_matches_with(
+        num_players,
+        &rules,
+        config,
+        num_matches,
+        seed,
+        |match_seed| {
+            strategies
+                .iter()
+                .enumerate()
+                .map(|(seat, name)| simulation::strategy_by_name(name, seat, match_seed).unwrap())
+                .collect::<Vec<Box<dyn Strategy>>>()
+        },
+        |match_index, round, state| {
+            game_rows.push(export::game_row(match_index, round, &strategies, state));
+            if turns {
+                turn_rows.extend(export::turn_rows(match_index, round, &state.history));
+            }
+            Ok(())
+        },
+    )
+    .map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+    let seats = strategies
+        .iter()
+        .enumerate()
+        .map(|(seat, name)| {
+            let d = PyDict::new(py);
+            d.set_item("strategy", name)?;
+            d.set_item("wins", report.wins[seat])?;
+            d.set_item("win_rate", report.win_rate(seat))?;
+            d.set_item("win_rate_interval", report.win_rate_interval(seat, 0.95))?;
+            d.set_item("mean_score", report.mean_score(seat))?;
+            d.set_item("mean_score_interval", report.mean_score_interval(seat, 0.95))?;
+            Ok(d)
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+    let games = game_rows.iter().map(|row| game_row_dict(py, row)).collect::<PyResult<Vec<_>>>()?;
+    let turns = turn_rows.iter().map(|row| turn_row_dict(py, row)).collect::<PyResult<Vec<_>>>()?;
+
+    let d = PyDict::new(py);
+    d.set_item("matches", report.matches)?;
+    d.set_item("rounds", report.rounds)?;
+    d.set_item("turn_capped_rounds", report.turn_capped_rounds)?;
+    d.set_item("seats", seats)?;
+    d.set_item("games", games)?;
+    d.set_item("turns", turns)?;
+    Ok(d)
+}
+
+// Flat like the CSV export, so each seat gets its own player_n and score_n
+// columns in a DataFrame
+fn game_row_dict<'py>(py: Python<'py>, row: &GameRow) -> PyResult<Bound<'py, PyDict>> {
+    let d = PyDict::new(py);
+    d.set_item("match_index", row.match_index)?;
+    d.set_item("round", row.round)?;
+    d.set_item("seed", row.seed)?;
+    d.set_item("dealer", row.dealer)?;
+    d.set_item("turns", row.turns)?;
+    d.set_item("outcome", &row.outcome)?;
+    d.set_item("went_out", row.went_out)?;
+    for (seat, (player, score)) in row.players.iter().zip(row.scores.iter()).enumerate() {
+        d.set_item(format!("player_{}", seat), player)?;
+        d.set_item(format!("score_{}", seat), score)?;
+    }
+    Ok(d)
+}
+
+// Plays are written the same as in the CSV export, e.g. "Multiple[7:S 7:H 7:C]"
+fn turn_row_dict<'py>(py: Python<'py>, row: &TurnRow) -> PyResult<Bound<'py, PyDict>> {
+    let plays: Vec<String> = row
+        .plays
+        .iter()
+        .map(|play| format!("{}[{}]", play.kind, play.cards.join(" ")))
+        .collect();
+    let d = PyDict::new(py);
+    d.set_item("match_index", row.match_index)?;
+    d.set_item("round", row.round)?;
+    d.set_item("turn", row.turn)?;
+    d.set_item("player", row.player)?;
+    d.set_item("hand_size", row.hand_size)?;
+    d.set_item("draw", &row.draw)?;
+    d.set_item("dig_depth", row.dig_depth)?;
+    d.set_item("plays", plays)?;
+    d.set_item("discard", &row.discard)?;
+    Ok(d)
+}
+
+#[pymodule]
+fn rummy_sim(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyCard>()?;
+    m.add_class::<PyCardSet>()?;
+    m.add_class::<PyPlayedCards>()?;
+    m.add_class::<PyRuleSet>()?;
+    m.add_class::<PyPlay>()?;
+    m.add_function(wrap_pyfunction!(all_possible_plays, m)?)?;
+    m.add_function(wrap_pyfunction!(full_deck, m)?)?;
+    m.add_function(wrap_pyfunction!(simulate_matches, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use crate::card::CardValue;
+
+// Knobs for the bits of rummy that every family seems to play differently.
+// RuleSet::default() is the 500 Rum we started with: sets and runs of 3+,
+// ace either low or high (but no K-A-2), and you can dig as deep into the
+// discard pile as you like. No wilds unless there are jokers in the deck, and
+// the round ends when the stock runs out. Tables of more than 4 get a second
+// deck shuffled in.
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct RuleSet {
+    // Which game this is. Most of the knobs below are for 500 Rum and its
+    // cousins, Gin has its own fixed rules (see gin::rules)
+    pub game: GameKind,
+
+    // Smallest number of cards that can be laid down as a new multiple / run
+    pub min_multiple_size: usize,
+    pub min_run_size: usize,
+
+    pub ace_rule: AceRule,
+
+    // Whether runs can go around the corner, e.g. Q-K-A-2
+    pub allow_wrap_around: bool,
+
+    // If false, only the top card of the discard pile can be taken
+    pub can_dig_discard_pile: bool,
+
+    // Whether the last card in hand has to be discarded to go out
+    // (as opposed to going out by melding everything)
+    pub final_discard_to_go_out: bool,
+
+    pub target_score: i32,
+
+    // Cards of this value can stand in for anything, same as a joker
+    // (e.g. twos wild, or Indian Rummy's cut joker)
+    pub wild_value: Option<CardValue>,
+
+    pub joker_points: u32,
+
+    // What happens when someone needs to draw and the stock's gone
+    pub stock_exhaustion: StockExhaustion,
+
+    // How many decks get shuffled together. None goes by the number of
+    // players: one deck for up to 4, two for up to 8, and so on
+    pub num_decks: Option<usize>,
+
+    // Shuffled in with each deck. They're wild, same as wild_value cards
+    pub jokers_per_deck: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GameKind {
+    // Meld as you go and score what's on the table minus what's left in hand
+    FiveHundred,
+    // Heads up, nothing goes on the table, and the round ends with a knock
+    Gin,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AceRule {
+    // A-2-3 only
+    Low,
+    // Q-K-A only
+    High,
+    // Either, but not both in the same run
+    LowOrHigh,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StockExhaustion {
+    // Round's over, everyone counts up what they have
+    EndRound,
+    // Everything in the discard pile but the top card gets shuffled and
+    // becomes the new stock
+    ReshuffleDiscards,
+    // Keep going, but the only place left to draw from is the discard pile
+    DiscardPileOnly,
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        RuleSet {
+            game: GameKind::FiveHundred,
+            min_multiple_size: 3,
+            min_run_size: 3,
+            ace_rule: AceRule::LowOrHigh,
+            allow_wrap_around: false,
+            can_dig_discard_pile: true,
+            final_discard_to_go_out: false,
+            target_score: 500,
+            wild_value: None,
+            joker_points: 15,
+            stock_exhaustion: StockExhaustion::EndRound,
+            num_decks: None,
+            jokers_per_deck: 0,
+        }
+    }
+}
+
+impl RuleSet {
+    pub fn ace_can_be_low(&self) -> bool {
+        self.allow_wrap_around || self.ace_rule != AceRule::High
+    }
+
+    pub fn ace_can_be_high(&self) -> bool {
+        self.allow_wrap_around || self.ace_rule != AceRule::Low
+    }
+
+    // How many decks a round with this many players gets dealt from
+    pub fn decks_for(&self, num_players: usize) -> usize {
+        self.num_decks.unwrap_or(num_players.div_ceil(4).max(1))
+    }
+}
@@ -1,4 +1,5 @@
-use crate::card::{Card, CardSet, CardSuit, CardValue, NUM_CARD_VALUES, NUM_SUITS};
+use crate::card::{Card, CardMultiset, CardSet, CardSuit, CardValue, NUM_CARD_VALUES};
+use crate::rules::RuleSet;
 
 const HIGH_ACE_INDEX: usize = 13;
 const LOW_ACE_INDEX: usize = 0;
@@ -19,16 +20,31 @@ const LOW_ACE_INDEX: usize = 0;
 // cards....... but this will mess with the fun calculation stuff i wanted 2 do with the hand.
 // o well
 
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Play {
     pub kind: PlayKind,
     pub cards_used: CardSet,
 
     // Cards that player will take on as part of doing this play
     // if they use a card past the top of the discard stack
-    pub cards_acquired: CardSet,
+    pub cards_acquired: CardMultiset,
+
+    // Wilds in this play and what each one stands for. A wild-value card used
+    // as a wild is in cards_used too (jokers aren't, they're not real cards)
+    pub wilds: Vec<WildUse>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WildUse {
+    // None for a joker, otherwise the wild-value card doing the standing in
+    pub wild: Option<Card>,
+    pub stands_for: Card,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PlayKind {
     StraightFlush {
         ace_status: Option<AceStatus>,
@@ -36,15 +52,25 @@ pub enum PlayKind {
     Multiple,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AceStatus {
     High,
     Low, 
 }
 
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PlayedCards {
     pub straight_flush_played: CardSet,
     pub multiple_played: CardSet,
+
+    // Wilds on the table and what they stand for. Wild-value cards being used
+    // as wilds go here and NOT in the sets above
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub straight_flush_wilds: Vec<WildUse>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub multiple_wilds: Vec<WildUse>,
 }
 
 pub struct PlayMetadata {
@@ -53,10 +79,43 @@ pub struct PlayMetadata {
 
 pub fn all_possible_plays(
     hand: &CardSet,
-    discard_pile: &Vec<Card>,
+    discard_pile: &[Card],
+    played_cards: &PlayedCards,
+    rules: &RuleSet,
+) -> Vec<Play> {
+    let source = CardSource { hand: *hand, discard_pile };
+    plays_from_source(&source, played_cards, rules)
+}
+
+// For games with more than one deck, where the hand and discard pile can have
+// repeats. Melds never use two of the exact same card, so the plays are the
+// same as for one of each, it's just where they come from that changes. The
+// table only holds one of each card per kind of meld though, so a second copy
+// of a card that's already in a run can't go in a run (same for multiples)
+pub fn all_possible_plays_multi(
+    hand: &CardMultiset,
+    discard_pile: &[Card],
     played_cards: &PlayedCards,
+    rules: &RuleSet,
 ) -> Vec<Play> {
-    let playable_cards = playable_cards(hand, discard_pile);
+    let source = CardSource { hand: hand.distinct(), discard_pile };
+    plays_from_source(&source, played_cards, rules)
+}
+
+// Where a play's cards can come from
+struct CardSource<'a> {
+    // One of each card in hand (plus jokers)
+    hand: CardSet,
+    discard_pile: &'a [Card],
+}
+
+fn plays_from_source(
+    source: &CardSource,
+    played_cards: &PlayedCards,
+    rules: &RuleSet,
+) -> Vec<Play> {
+    let hand = &source.hand;
+    let playable_cards = playable_cards(hand, reachable_discards(source.discard_pile, rules));
     let mut plays: Vec<Play> = vec![];
 
     // 1. Multiples
@@ -64,9 +123,9 @@ pub fn all_possible_plays(
         // for each V
         // check number of cards we have with value V
         // if 1: check if theres a V in played multiple cat
-        // if 2: cant play anything
-        // if 3: can play!! this exact combo of 3
-        // if 4: can play every combo of suits:
+        // if less than the minimum (usually 3): cant play anything
+        // otherwise: can play every combo of suits at least the minimum big
+        // e.g. with all 4 and a minimum of 3:
         //      012
         //      013
         //      023
@@ -81,50 +140,42 @@ pub fn all_possible_plays(
         let matching_suits = all_suits_with_value(&playable_cards, value);
         let num_suits = matching_suits.len();
 
-        match num_suits {
-            0 | 2 => (),
-            1 => {
-                if played_cards.value_was_played_as_multiple(value) {
-                    let suit = matching_suits.last().unwrap().clone();
-                    let card = Card { value, suit };
-
-                    let mut cards_used = CardSet::new();
-                    cards_used.add(&card);
-
-                    let play = Play::make(cards_used, PlayKind::Multiple, discard_pile);
-                    plays.push(play);
+        // Anything that isn't on the table yet can go on one at a time. With
+        // one deck that's never more than one card, more decks can have a few
+        if played_cards.value_was_played_as_multiple(value, rules) {
+            for suit in matching_suits.iter() {
+                let card = Card { value, suit: *suit };
+                if played_cards.multiple_contains(&card) {
+                    continue;
                 }
-            },
-            3 => {
+
                 let mut cards_used = CardSet::new();
-                for suit in matching_suits.iter() {
-                    let card = Card { value, suit: suit.clone() };
-                    cards_used.add(&card);
-                }
-                let play = Play::make(cards_used, PlayKind::Multiple, discard_pile);
+                cards_used.add(&card);
+
+                let play = Play::make(cards_used, PlayKind::Multiple, source);
                 plays.push(play);
-            },
-            4 => {
-                let mut all_three_card_move_sets: Vec<CardSet> = vec![CardSet::new(); 4];
-                let mut four_card_move_set = CardSet::new();
-                for i in 0..4 {
-                    let suit = CardSuit::from_index(i);
-                    let card = Card { value, suit };
-                    for j in 0..4 {
-                        if i != j {
-                            all_three_card_move_sets[j].add(&card);
-                        }
+            }
+            continue;
+        }
+
+        match num_suits {
+            0 | 1 => (),
+            2..=4 => {
+                // Each bit of the mask says whether to use that suit
+                for mask in 1..(1usize << num_suits) {
+                    if (mask.count_ones() as usize) < rules.min_multiple_size {
+                        continue;
                     }
-                    four_card_move_set.add(&card);
-                }
 
-                for three_card_set in all_three_card_move_sets.into_iter() {
-                    let play = Play::make(three_card_set, PlayKind::Multiple, discard_pile);
+                    let mut cards_used = CardSet::new();
+                    for (i, suit) in matching_suits.iter().enumerate() {
+                        if mask & (1 << i) != 0 {
+                            cards_used.add(&Card { value, suit: *suit });
+                        }
+                    }
+                    let play = Play::make(cards_used, PlayKind::Multiple, source);
                     plays.push(play);
                 }
-
-                let four_play = Play::make(four_card_move_set, PlayKind::Multiple, discard_pile);
-                plays.push(four_play);
             },
             _ => panic!("Somehow more than 4 suits represented within hand: {:?}", matching_suits),
         }
@@ -162,24 +213,28 @@ pub fn all_possible_plays(
 
             let next_card = Card { suit, value: value.next() };
             let prev_card = Card { suit, value: value.prev() };
-            if played_cards.straight_flush_played.contains(&next_card) {
+            let next_is_played = played_cards.straight_flush_contains(&next_card);
+            let prev_is_played = played_cards.straight_flush_contains(&prev_card);
+            if next_is_played && can_be_adjacent(&card, &next_card, rules) {
                 generate_straight_extensions(
                     &playable_cards, 
                     &card, 
                     &prev_card, 
                     AceStatus::Low, 
+                    rules,
                     &mut plays, 
-                    discard_pile,
+                    source,
                 );
             }
-            else if played_cards.straight_flush_played.contains(&prev_card) {
+            else if prev_is_played && can_be_adjacent(&prev_card, &card, rules) {
                 generate_straight_extensions(
                     &playable_cards, 
                     &card, 
                     &next_card, 
                     AceStatus::High, 
+                    rules,
                     &mut plays, 
-                    discard_pile,
+                    source,
                 );
             }
         }
@@ -187,131 +242,442 @@ pub fn all_possible_plays(
     
     // 2b. Standalone straights
     for suit in CardSuit::iter() {
-        for value_index in LOW_ACE_INDEX..HIGH_ACE_INDEX {
-            let has_card = bounded_cardset_contains(&playable_cards, suit, value_index);
-            let has_next = bounded_cardset_contains(&playable_cards, suit, value_index + 1);
-            let next_has_next = bounded_cardset_contains(&playable_cards, suit, value_index + 2);
-            if has_card && has_next && next_has_next {
-                generate_standalone_plays(
+        for start_index in run_start_indices(rules) {
+            generate_standalone_plays(
+                &playable_cards, 
+                suit, 
+                start_index, 
+                rules,
+                &mut plays,
+                source,
+            );
+        }
+    }
+
+    // 3. Plays with wilds
+    // Same idea as above but gaps can be filled in with jokers or wild-value
+    // cards. Every meld still needs at least one real card in it
+    let wilds = available_wilds(hand, &playable_cards, rules);
+    if !wilds.is_empty() {
+        generate_wild_layoffs(played_cards, &wilds, rules, &mut plays, source);
+
+        for value in CardValue::iter() {
+            generate_wild_multiples(&playable_cards, value, &wilds, rules, &mut plays, source);
+        }
+
+        for suit in CardSuit::iter() {
+            for start_index in run_start_indices(rules) {
+                generate_wild_runs(
                     &playable_cards, 
                     suit, 
-                    value_index, 
-                    &mut plays,
-                    &discard_pile,
+                    start_index, 
+                    &wilds, 
+                    rules, 
+                    &mut plays, 
+                    source,
                 );
             }
         }
     }
 
+    // Only possible with more than one deck, see all_possible_plays_multi
+    plays.retain(|play| !repeats_table_card(play, played_cards));
     plays
 }
 
+// Whether the play would put a card (or a wild standing for one) into a run
+// or multiple when that card's already in one
+fn repeats_table_card(play: &Play, played_cards: &PlayedCards) -> bool {
+    let on_table = |card: &Card| match play.kind {
+        PlayKind::StraightFlush { .. } => played_cards.straight_flush_contains(card),
+        PlayKind::Multiple => played_cards.multiple_contains(card),
+    };
+    let mut naturals = play.cards_used.as_ordered_list().into_iter()
+        .filter(|card| !play.wilds.iter().any(|w| w.wild == Some(*card)));
+    naturals.any(|card| on_table(&card)) || play.wilds.iter().any(|w| on_table(&w.stands_for))
+}
+
+// Whether `lower` can sit directly below `upper` in a run, i.e. K-A needs aces
+// high and A-2 needs aces low (wrap-around allows both)
+fn can_be_adjacent(lower: &Card, upper: &Card, rules: &RuleSet) -> bool {
+    match (lower.value, upper.value) {
+        (CardValue::King, CardValue::Ace) => rules.ace_can_be_high(),
+        (CardValue::Ace, CardValue::Two) => rules.ace_can_be_low(),
+        _ => true,
+    }
+}
+
 fn generate_straight_extensions(
     playable_cards: &CardSet, 
     card: &Card, 
     additional_card: &Card, 
     ace_status_value: AceStatus,
+    rules: &RuleSet,
     plays: &mut Vec<Play>,
-    discard_pile: &Vec<Card>,
+    source: &CardSource,
 ) {
 
     let mut cards_used = CardSet::new();
-    cards_used.add(&card);
+    cards_used.add(card);
 
     let ace_status = match card.value {
-        CardValue::Ace => Some(ace_status_value),
+        CardValue::Ace => Some(ace_status_value.clone()),
         _ => None,
     };
-    let value_is_not_ace = ace_status.is_none();
-    let kind = PlayKind::StraightFlush { ace_status };
+    let kind = PlayKind::StraightFlush { ace_status: ace_status.clone() };
 
-    if value_is_not_ace && playable_cards.contains(additional_card) {
-        let mut other_cards_used = cards_used.clone();
+    // Going past an ace means going around the corner (K-A-2)
+    let can_go_past_card = ace_status.is_none() || rules.allow_wrap_around;
+    let (lower, upper) = match ace_status_value {
+        AceStatus::Low => (additional_card, card),
+        AceStatus::High => (card, additional_card),
+    };
+    if can_go_past_card 
+        && can_be_adjacent(lower, upper, rules) 
+        && playable_cards.contains(additional_card) 
+    {
+        let mut other_cards_used = cards_used;
         other_cards_used.add(additional_card);
-        let play = Play::make(other_cards_used, kind.clone(), discard_pile);
+        let other_ace_status = match (card.value, additional_card.value) {
+            (_, CardValue::Ace) => Some(ace_status_value),
+            // K-A-2 going down from the 2, so the ace is after a king
+            (CardValue::Ace, _) => Some(AceStatus::High),
+            _ => None,
+        };
+        let other_kind = PlayKind::StraightFlush { ace_status: other_ace_status };
+        let play = Play::make(other_cards_used, other_kind, source);
         plays.push(play);
     }
 
-    let play = Play::make(cards_used, kind, discard_pile);
+    let play = Play::make(cards_used, kind, source);
     plays.push(play);
 }
 
+// Every run of at least the minimum length starting at start_index and going up
 fn generate_standalone_plays(
     playable_cards: &CardSet, 
     suit: CardSuit, 
-    value_index: usize, 
+    start_index: usize, 
+    rules: &RuleSet,
     plays: &mut Vec<Play>,
-    discard_pile: &Vec<Card>,
+    source: &CardSource,
 ) {
-    let card_of_index = |i| {
-        let mod_index = mod_value_index(i);
-        let value = CardValue::from_index(mod_index);
-        Card { suit, value }
-    };
+    let last_index = last_run_index(start_index, rules);
 
-    let mut first_set = CardSet::new();
-    let mut first_ace_status: Option<AceStatus> = None;
-    for i in value_index..(value_index + 3) {
-        let single_ace_status = ace_status_of_index(i);
-        first_ace_status = first_ace_status.or(single_ace_status);
+    let mut cards_used = CardSet::new();
+    let mut ace_status: Option<AceStatus> = None;
+    for index in start_index..=last_index {
+        let card = card_of_index(suit, index);
 
-        let card = card_of_index(i);
-        first_set.add(&card);
+        // Covers the VERY SPECIFIC case where a player somehow has access to
+        // an entire straight from the first ace to the last. The ace can't be
+        // used twice so the run stops at the king
+        if !playable_cards.contains(&card) || cards_used.contains(&card) {
+            break;
+        }
+        cards_used.add(&card);
+
+        let single_ace_status = ace_status_of_index(index);
+        ace_status = ace_status.or(single_ace_status);
+
+        // With wrap-around, all 13 cards make the same run no matter where it
+        // starts, so only count it once
+        let run_length = index - start_index + 1;
+        let is_repeat_full_run = rules.allow_wrap_around 
+            && run_length == NUM_CARD_VALUES 
+            && start_index != LOW_ACE_INDEX;
+
+        if run_length >= rules.min_run_size && !is_repeat_full_run {
+            let kind = PlayKind::StraightFlush { ace_status: ace_status.clone() };
+            let play = Play::make(cards_used, kind, source);
+            plays.push(play);
+        }
     }
-    
-    let mut play_infos = vec![(first_set, first_ace_status)];
+}
 
-    let mut extra_index = value_index + 3;
-    while bounded_cardset_contains(playable_cards, suit, extra_index) {
-        let (mut set, ace_status) = play_infos.last().unwrap().clone();
+// Jokers (None) first, then wild-value cards already in hand, then ones that
+// would mean digging into the discard pile
+fn available_wilds(hand: &CardSet, playable_cards: &CardSet, rules: &RuleSet) -> Vec<Option<Card>> {
+    let mut wilds = vec![None; playable_cards.num_jokers()];
+    if let Some(value) = rules.wild_value {
+        let mut discarded_wilds = vec![];
+        for suit in CardSuit::iter() {
+            let card = Card { suit, value };
+            if hand.contains(&card) {
+                wilds.push(Some(card));
+            } else if playable_cards.contains(&card) {
+                discarded_wilds.push(Some(card));
+            }
+        }
+        wilds.extend(discarded_wilds);
+    }
+    wilds
+}
 
-        let card = card_of_index(extra_index);
+fn generate_wild_layoffs(
+    played_cards: &PlayedCards,
+    wilds: &[Option<Card>],
+    rules: &RuleSet,
+    plays: &mut Vec<Play>,
+    source: &CardSource,
+) {
+    let wild = wilds[0];
+    let mut cards_used = CardSet::new();
+    if let Some(card) = wild {
+        cards_used.add(&card);
+    }
 
-        // Covers the VERY SPECIFIC case where a player somehow has access to
-        // an entire straight from the first ace to the last. In this scenario,
-        // ace will have been added twice...
-        if set.contains(&card) {
-            match card.value {
-                CardValue::Ace => (),
-                _ => panic!("Non-ace card {} being added twice to a cardset {}", card, set),
+    // A wild can go on either end of a run on the table...
+    for suit in CardSuit::iter() {
+        for value in CardValue::iter() {
+            let card = Card { suit, value };
+            if played_cards.straight_flush_contains(&card) {
+                continue;
+            }
+
+            let next_card = Card { suit, value: value.next() };
+            let prev_card = Card { suit, value: value.prev() };
+            let (ace_status, fits) = if played_cards.straight_flush_contains(&next_card) {
+                (AceStatus::Low, can_be_adjacent(&card, &next_card, rules))
+            } else if played_cards.straight_flush_contains(&prev_card) {
+                (AceStatus::High, can_be_adjacent(&prev_card, &card, rules))
+            } else {
+                (AceStatus::Low, false)
+            };
+            if !fits {
+                continue;
             }
+
+            let ace_status = match value {
+                CardValue::Ace => Some(ace_status),
+                _ => None,
+            };
+            let kind = PlayKind::StraightFlush { ace_status };
+            let wild_uses = vec![WildUse { wild, stands_for: card }];
+            let play = Play::make_with_wilds(cards_used, kind, wild_uses, source);
+            plays.push(play);
         }
-        else {
-            set.add(&card);
+    }
+
+    // ...or fill in a missing suit of a multiple
+    for value in CardValue::iter() {
+        if !played_cards.value_was_played_as_multiple(value, rules) {
+            continue;
+        }
+        for suit in CardSuit::iter() {
+            let card = Card { suit, value };
+            if played_cards.multiple_contains(&card) {
+                continue;
+            }
+            let wild_uses = vec![WildUse { wild, stands_for: card }];
+            let play = Play::make_with_wilds(
+                cards_used, 
+                PlayKind::Multiple, 
+                wild_uses, 
+                source,
+            );
+            plays.push(play);
+            break;
         }
+    }
+}
+
+fn generate_wild_multiples(
+    playable_cards: &CardSet,
+    value: CardValue,
+    wilds: &[Option<Card>],
+    rules: &RuleSet,
+    plays: &mut Vec<Play>,
+    source: &CardSource,
+) {
+    // All wilds anyway, nothing to fill in
+    if rules.wild_value == Some(value) {
+        return;
+    }
+
+    let matching_suits = all_suits_with_value(playable_cards, value);
+    for mask in 1..(1usize << matching_suits.len()) {
+        let natural_suits: Vec<CardSuit> = matching_suits.iter()
+            .enumerate()
+            .filter(|(i, _)| mask & (1 << i) != 0)
+            .map(|(_, suit)| *suit)
+            .collect();
+
+        // Wilds stand in for suits nobody can use first, then for ones the
+        // player has but is keeping out of this play
+        let mut missing_suits: Vec<CardSuit> = CardSuit::iter()
+            .filter(|suit| !matching_suits.contains(suit))
+            .collect();
+        missing_suits.extend(matching_suits.iter().filter(|suit| !natural_suits.contains(suit)));
+
+        let max_wilds = missing_suits.len().min(wilds.len());
+        for num_wilds in 1..=max_wilds {
+            if natural_suits.len() + num_wilds < rules.min_multiple_size {
+                continue;
+            }
 
-        let single_ace_status = ace_status_of_index(extra_index);
-        let ace_status = ace_status.or(single_ace_status);
+            let mut cards_used = CardSet::new();
+            for suit in natural_suits.iter() {
+                cards_used.add(&Card { value, suit: *suit });
+            }
 
-        play_infos.push((set, ace_status));
+            let mut wild_uses = vec![];
+            for (wild, suit) in wilds.iter().zip(missing_suits.iter()).take(num_wilds) {
+                if let Some(card) = wild {
+                    cards_used.add(card);
+                }
+                wild_uses.push(WildUse { wild: *wild, stands_for: Card { value, suit: *suit } });
+            }
 
-        extra_index += 1;
+            let play = Play::make_with_wilds(cards_used, PlayKind::Multiple, wild_uses, source);
+            plays.push(play);
+        }
     }
+}
 
-    for (cards_used, ace_status) in play_infos.into_iter() {
-        let kind = PlayKind::StraightFlush { ace_status };
-        let play = Play::make(cards_used, kind, discard_pile);
+// Same as generate_standalone_plays except gaps get filled with wilds
+fn generate_wild_runs(
+    playable_cards: &CardSet,
+    suit: CardSuit,
+    start_index: usize,
+    wilds: &[Option<Card>],
+    rules: &RuleSet,
+    plays: &mut Vec<Play>,
+    source: &CardSource,
+) {
+    let last_index = last_run_index(start_index, rules).min(start_index + NUM_CARD_VALUES - 1);
+
+    let mut naturals = CardSet::new();
+    let mut missing: Vec<Card> = vec![];
+    let mut ace_status: Option<AceStatus> = None;
+    for index in start_index..=last_index {
+        let card = card_of_index(suit, index);
+        if playable_cards.contains(&card) {
+            naturals.add(&card);
+        } else {
+            missing.push(card);
+        }
+        ace_status = ace_status.or(ace_status_of_index(index));
+
+        // More gaps only ever shows up further along
+        if missing.len() > wilds.len() {
+            break;
+        }
+
+        let run_length = index - start_index + 1;
+        let is_repeat_full_run = rules.allow_wrap_around 
+            && run_length == NUM_CARD_VALUES 
+            && start_index != LOW_ACE_INDEX;
+        let has_natural = !naturals.as_ordered_list().is_empty();
+        if missing.is_empty() || !has_natural || run_length < rules.min_run_size || is_repeat_full_run {
+            continue;
+        }
+
+        // A wild-value card sitting in its own spot can't also be a wild
+        let free_wilds: Vec<Option<Card>> = wilds.iter()
+            .filter(|wild| match wild {
+                Some(card) => !naturals.contains(card),
+                None => true,
+            })
+            .copied()
+            .collect();
+        if missing.len() > free_wilds.len() {
+            continue;
+        }
+
+        let mut cards_used = naturals;
+        let mut wild_uses = vec![];
+        for (wild, stands_for) in free_wilds.iter().zip(missing.iter()) {
+            if let Some(card) = wild {
+                cards_used.add(card);
+            }
+            wild_uses.push(WildUse { wild: *wild, stands_for: *stands_for });
+        }
+
+        let kind = PlayKind::StraightFlush { ace_status: ace_status.clone() };
+        let play = Play::make_with_wilds(cards_used, kind, wild_uses, source);
         plays.push(play);
     }
 }
 
 impl Play {
-    fn make(cards_used: CardSet, kind: PlayKind, discard_pile: &Vec<Card>) -> Play {
-        let mut cards_acquired = CardSet::new();
+    fn make_with_wilds(
+        cards_used: CardSet, 
+        kind: PlayKind, 
+        wilds: Vec<WildUse>, 
+        source: &CardSource,
+    ) -> Play {
+        let mut play = Play::make(cards_used, kind, source);
+        play.wilds = wilds;
+        play
+    }
 
-        let mut in_adding_mode = false;
-        for discarded_card in discard_pile.iter() {
-            if !in_adding_mode && cards_used.contains(discarded_card) {
-                in_adding_mode = true;
+    fn make(cards_used: CardSet, kind: PlayKind, source: &CardSource) -> Play {
+        // Have to dig down to the deepest card the play needs that isn't already
+        // in hand. If there's more than one copy in the pile, the highest one will do
+        let mut dig_index: Option<usize> = None;
+        for card in cards_used.as_ordered_list().iter() {
+            if source.hand.contains(card) {
+                continue;
+            }
+            if let Some(i) = source.discard_pile.iter().rposition(|c| c == card) {
+                dig_index = Some(dig_index.map_or(i, |d| d.min(i)));
             }
+        }
 
-            if in_adding_mode {
+        let mut cards_acquired = CardMultiset::new();
+        if let Some(i) = dig_index {
+            for discarded_card in source.discard_pile[i..].iter() {
                 cards_acquired.add(discarded_card);
             }
         }
 
-        Play { cards_used, cards_acquired, kind }
+        Play { cards_used, cards_acquired, kind, wilds: vec![] }
+    }
+
+    // What laying this down scores in 500 Rum. An ace only counts 5 when it's
+    // the low end of a run
+    pub fn points(&self, rules: &RuleSet) -> u32 {
+        let mut points = 0;
+        for card in self.cards_used.as_ordered_list().iter() {
+            let is_wild = self.wilds.iter().any(|w| w.wild == Some(*card));
+            points += if is_wild {
+                rules.joker_points
+            } else if card.value == CardValue::Ace
+                && self.kind == (PlayKind::StraightFlush { ace_status: Some(AceStatus::Low) })
+            {
+                5
+            } else {
+                card_points(card)
+            };
+        }
+        let num_jokers = self.wilds.iter().filter(|w| w.wild.is_none()).count() as u32;
+        points + num_jokers * rules.joker_points
+    }
+}
+
+// 500 Rum card values: aces 15, tens and face cards 10, everything else 5
+pub fn card_points(card: &Card) -> u32 {
+    use CardValue::*;
+    match card.value {
+        Ace => 15,
+        Ten | Jack | Queen | King => 10,
+        _ => 5,
+    }
+}
+
+// What a hand costs if the round ends with it still in hand. Wild-value cards
+// count the same as jokers
+pub fn hand_points(hand: &CardMultiset, rules: &RuleSet) -> u32 {
+    let mut points = 0;
+    for card in hand.as_ordered_list().iter() {
+        points += if Some(card.value) == rules.wild_value {
+            rules.joker_points
+        } else {
+            card_points(card)
+        };
     }
+    points + hand.num_jokers() as u32 * rules.joker_points
 }
 
 fn all_suits_with_value(s: &CardSet, value: CardValue) -> Vec<CardSuit> {
@@ -326,46 +692,182 @@ fn all_suits_with_value(s: &CardSet, value: CardValue) -> Vec<CardSuit> {
 }
 
 impl PlayedCards {
-    fn value_was_played_as_multiple(&self, value: CardValue) -> bool {
-        let mut num_times = 0;
-        for suit in CardSuit::iter() {
-            let card = Card { value, suit };
-            if self.multiple_played.contains(&card) {
-                num_times += 1;
+    // Whether the card is in a run on the table, for real or as a wild
+    pub fn straight_flush_contains(&self, card: &Card) -> bool {
+        self.straight_flush_played.contains(card)
+            || self.straight_flush_wilds.iter().any(|w| w.stands_for == *card)
+    }
+
+    pub fn multiple_contains(&self, card: &Card) -> bool {
+        self.multiple_played.contains(card)
+            || self.multiple_wilds.iter().any(|w| w.stands_for == *card)
+    }
+
+    // Puts a play's cards on the table. Wild-value cards standing in for
+    // something only go in the wilds lists
+    pub fn add_play(&mut self, play: &Play) {
+        let (played, wilds) = match play.kind {
+            PlayKind::StraightFlush { .. } => {
+                (&mut self.straight_flush_played, &mut self.straight_flush_wilds)
+            },
+            PlayKind::Multiple => (&mut self.multiple_played, &mut self.multiple_wilds),
+        };
+        for card in play.cards_used.as_ordered_list().iter() {
+            if !play.wilds.iter().any(|w| w.wild == Some(*card)) {
+                played.add(card);
             }
         }
+        wilds.extend(play.wilds.iter().copied());
+    }
+
+    // A multiple on the table has at least rules.min_multiple_size suits in
+    // it, so anything between none and that can't happen
+    fn value_was_played_as_multiple(&self, value: CardValue, rules: &RuleSet) -> bool {
+        let num_times = self.num_played_as_multiple(value);
 
         match num_times {
             0 => false,
-            3 | 4 => true,
+            n if n >= rules.min_multiple_size => true,
             _ => panic!(
                 "Invalid game state: {} cards of value {:?} were played as multiples",
                 num_times, value,
             ),
         }
     }
+
+    fn num_played_as_multiple(&self, value: CardValue) -> usize {
+        CardSuit::iter()
+            .filter(|suit| self.multiple_contains(&Card { value, suit: *suit }))
+            .count()
+    }
+}
+
+// all_possible_plays panics on game states that can't happen, which is fine when
+// the engine built the position but not when it came from somewhere else (like
+// over the network). This checks the same things up front and says what's wrong.
+// Each card can turn up once per deck
+pub fn validate_position(
+    hand: &CardSet,
+    discard_pile: &[Card],
+    played_cards: &PlayedCards,
+    rules: &RuleSet,
+    num_decks: usize,
+) -> Result<(), String> {
+    let mut seen = CardMultiset::new();
+    let all_cards = hand.as_ordered_list().into_iter()
+        .chain(discard_pile.iter().copied())
+        .chain(played_cards.straight_flush_played.as_ordered_list())
+        .chain(played_cards.multiple_played.as_ordered_list())
+        .chain(played_cards.straight_flush_wilds.iter().filter_map(|w| w.wild))
+        .chain(played_cards.multiple_wilds.iter().filter_map(|w| w.wild));
+    for card in all_cards {
+        if seen.count(&card) >= num_decks {
+            let limit = if num_decks == 1 { "once".to_string() } else { format!("{} times", num_decks) };
+            return Err(format!("Card {} appears more than {} in the position", card, limit));
+        }
+        seen.add(&card);
+    }
+
+    for value in CardValue::iter() {
+        let num_times = played_cards.num_played_as_multiple(value);
+        if num_times > 0 && num_times < rules.min_multiple_size {
+            return Err(format!(
+                "{} cards of value {:?} were played as multiples",
+                num_times, value,
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// What kind of meld a group of cards makes all on its own, if any
+pub fn meld_kind(cards: &CardSet, rules: &RuleSet) -> Option<PlayKind> {
+    let card_list = cards.as_ordered_list();
+    let first = *card_list.first()?;
+    let num_cards = card_list.len();
+
+    if card_list.iter().all(|c| c.value == first.value) {
+        if num_cards >= rules.min_multiple_size {
+            return Some(PlayKind::Multiple);
+        }
+        return None;
+    }
+
+    let same_suit = card_list.iter().all(|c| c.suit == first.suit);
+    if !same_suit || num_cards < rules.min_run_size || num_cards > NUM_CARD_VALUES {
+        return None;
+    }
+
+    // Try every place the run could start. The cards are all different, so if
+    // every card in the stretch is there then that's the whole set
+    for start_index in run_start_indices(rules) {
+        let end_index = start_index + num_cards - 1;
+        if end_index > last_run_index(start_index, rules) {
+            continue;
+        }
+
+        let mut ace_status = None;
+        let mut is_run = true;
+        for index in start_index..=end_index {
+            if !cards.contains(&card_of_index(first.suit, index)) {
+                is_run = false;
+                break;
+            }
+            ace_status = ace_status.or(ace_status_of_index(index));
+        }
+
+        if is_run {
+            return Some(PlayKind::StraightFlush { ace_status });
+        }
+    }
+
+    None
 }
 
-fn playable_cards(hand: &CardSet, discard_pile: &Vec<Card>) -> CardSet {
-    let mut s = hand.clone();
+// One of each card that could go into a play
+fn playable_cards(hand: &CardSet, discard_pile: &[Card]) -> CardSet {
+    let mut s = *hand;
     for card in discard_pile.iter() {
-        s.add(card);
+        if !s.contains(card) {
+            s.add(card);
+        }
     }
     s
 }
 
-fn bounded_cardset_contains(set: &CardSet, suit: CardSuit, index: usize) -> bool {
-    if index > HIGH_ACE_INDEX { 
-        return false; 
+// Index 13 is the high ace, which only exists if aces can go high. With
+// wrap-around there's no top so every value can start a run
+fn run_start_indices(rules: &RuleSet) -> std::ops::RangeInclusive<usize> {
+    let first_start_index = if rules.ace_can_be_low() { LOW_ACE_INDEX } else { LOW_ACE_INDEX + 1 };
+    let last_start_index = if rules.allow_wrap_around { NUM_CARD_VALUES - 1 } else { HIGH_ACE_INDEX };
+    first_start_index..=last_start_index
+}
+
+// Furthest a run starting at start_index can go
+fn last_run_index(start_index: usize, rules: &RuleSet) -> usize {
+    if rules.allow_wrap_around {
+        start_index + NUM_CARD_VALUES - 1
+    } else if rules.ace_can_be_high() {
+        HIGH_ACE_INDEX
+    } else {
+        HIGH_ACE_INDEX - 1
     }
+}
 
-    let mod_index = mod_value_index(index);
-    let card = Card { suit, value: CardValue::from_index(mod_index) };
-    set.contains(&card)
+// Indices past the king go around again, so 13 is the high ace
+fn card_of_index(suit: CardSuit, index: usize) -> Card {
+    let value = CardValue::from_index(index % NUM_CARD_VALUES);
+    Card { suit, value }
 }
 
-fn mod_value_index(value_index: usize) -> usize {
-    if value_index == HIGH_ACE_INDEX { LOW_ACE_INDEX } else { value_index }
+// The cards in the discard pile a player is allowed to pick from
+fn reachable_discards<'a>(discard_pile: &'a [Card], rules: &RuleSet) -> &'a [Card] {
+    if rules.can_dig_discard_pile || discard_pile.is_empty() {
+        discard_pile
+    } else {
+        &discard_pile[discard_pile.len() - 1..]
+    }
 }
 
 fn ace_status_of_index(value_index: usize) -> Option<AceStatus> {
@@ -375,3 +877,53 @@ fn ace_status_of_index(value_index: usize) -> Option<AceStatus> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cards(s: &str) -> CardSet {
+        let cards: Vec<Card> = s.split_whitespace().map(|c| c.parse().unwrap()).collect();
+        CardSet::from_vec(&cards)
+    }
+
+    fn pair_of_sevens_on_the_table() -> PlayedCards {
+        PlayedCards { multiple_played: cards("7:H 7:S"), ..PlayedCards::default() }
+    }
+
+    #[test]
+    fn pairs_count_as_multiples_when_the_rules_allow_them() {
+        let rules = RuleSet { min_multiple_size: 2, ..RuleSet::default() };
+        let played = pair_of_sevens_on_the_table();
+        assert!(validate_position(&cards("7:C"), &[], &played, &rules, 1).is_ok());
+
+        let plays = all_possible_plays(&cards("7:C 9:D"), &[], &played, &rules);
+        let laid_off: Vec<String> = plays
+            .iter()
+            .filter(|play| play.kind == PlayKind::Multiple)
+            .map(|play| play.cards_used.to_string())
+            .collect();
+        assert_eq!(laid_off, vec![cards("7:C").to_string()]);
+    }
+
+    #[test]
+    fn a_multiple_smaller_than_the_rules_allow_is_invalid() {
+        let played = pair_of_sevens_on_the_table();
+        assert!(validate_position(&cards("7:C"), &[], &played, &RuleSet::default(), 1).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid game state")]
+    fn generating_plays_on_a_short_multiple_panics() {
+        all_possible_plays(&cards("7:C"), &[], &pair_of_sevens_on_the_table(), &RuleSet::default());
+    }
+
+    #[test]
+    fn duplicates_are_allowed_up_to_the_number_of_decks() {
+        let rules = RuleSet::default();
+        let discard_pile: Vec<Card> = vec!["5:D".parse().unwrap()];
+        let played = PlayedCards::default();
+        assert!(validate_position(&cards("5:D"), &discard_pile, &played, &rules, 1).is_err());
+        assert!(validate_position(&cards("5:D"), &discard_pile, &played, &rules, 2).is_ok());
+    }
+}
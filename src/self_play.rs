@@ -0,0 +1,294 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::card::Card;
+use crate::export::ExportFormat;
+use crate::features;
+use crate::game::{self, Action, Draw, GameEvent, PlayerView, Strategy};
+use crate::game_match::{Match, MatchConfig};
+use crate::monte_carlo::margin;
+use crate::rules::RuleSet;
+use crate::score::Play;
+
+// Training data from bots playing each other. Every decision a player makes
+// becomes a record: the features of what they could see (features::encode),
+// the action they took (features::encode_action), and how the round turned out
+// for them, as points ahead of the rest of the table.
+//
+// Deciding to stop playing and discard shows up as just the discard, so the
+// actions recorded line up with PlayerView::legal_actions.
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Sample {
+    pub match_index: usize,
+    pub round: usize,
+    pub player: usize,
+    pub features: Vec<f32>,
+    pub action: Vec<f32>,
+    // Filled in when the round's over
+    pub outcome: f64,
+}
+
+// What the recorders write into and the generator reads out of
+#[derive(Default)]
+struct Log {
+    match_index: usize,
+    round: usize,
+    match_scores: Vec<i32>,
+    samples: Vec<Sample>,
+}
+
+// Wraps a strategy and writes down everything it decides
+struct Recorder {
+    inner: Box<dyn Strategy>,
+    seat: usize,
+    log: Rc<RefCell<Log>>,
+    // Where this round's samples start in the log
+    round_start: usize,
+}
+
+impl Recorder {
+    fn record(&mut self, view: &PlayerView, action: &Action) {
+        let mut log = self.log.borrow_mut();
+        let sample = Sample {
+            match_index: log.match_index,
+            round: log.round,
+            player: self.seat,
+            features: features::encode(view, &log.match_scores),
+            action: features::encode_action(action, view),
+            outcome: 0.0,
+        };
+        log.samples.push(sample);
+    }
+}
+
+impl Strategy for Recorder {
+    fn choose_draw(&mut self, view: &PlayerView) -> Draw {
+        let draw = self.inner.choose_draw(view);
+        self.record(view, &Action::Draw(draw.clone()));
+        draw
+    }
+
+    fn choose_play(&mut self, view: &PlayerView) -> Option<Play> {
+        let play = self.inner.choose_play(view);
+        if let Some(play) = &play {
+            self.record(view, &Action::Play(Box::new(play.clone())));
+        }
+        play
+    }
+
+    fn choose_discard(&mut self, view: &PlayerView) -> Card {
+        let card = self.inner.choose_discard(view);
+        // Otherwise it gets recorded once it's known whether it's a knock
+        if !view.can_knock_with(&card) {
+            self.record(view, &Action::Discard(card));
+        }
+        card
+    }
+
+    fn choose_knock(&mut self, view: &PlayerView, card: &Card) -> bool {
+        let knock = self.inner.choose_knock(view, card);
+        let action = if knock { Action::Knock(*card) } else { Action::Discard(*card) };
+        self.record(view, &action);
+        knock
+    }
+
+    fn observe(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::RoundStarted { .. } => {
+                self.round_start = self.log.borrow().samples.len();
+            },
+            GameEvent::RoundOver { scores, .. } => {
+                let outcome = margin(scores, self.seat);
+                let mut log = self.log.borrow_mut();
+                for sample in log.samples[self.round_start..].iter_mut() {
+                    if sample.player == self.seat {
+                        sample.outcome = outcome;
+                    }
+                }
+            },
+            _ => {},
+        }
+        self.inner.observe(event);
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct SelfPlayConfig {
+    pub num_players: usize,
+    pub num_matches: usize,
+    pub match_config: MatchConfig,
+    pub format: ExportFormat,
+}
+
+impl Default for SelfPlayConfig {
+    fn default() -> Self {
+        SelfPlayConfig {
+            num_players: 2,
+            num_matches: 100,
+            match_config: MatchConfig::default(),
+            format: ExportFormat::Csv,
+        }
+    }
+}
+
+// Plays the matches and writes a record for every decision, a line each.
+// Returns how many got written. `make_strategy` gets the seat and the match
+// seed. Too many players for the rules is an InvalidInput error
+pub fn generate<F, W>(
+    rules: &RuleSet,
+    config: SelfPlayConfig,
+    seed: u64,
+    mut make_strategy: F,
+    out: &mut W,
+) -> io::Result<usize>
+where
+    F: FnMut(usize, u64) -> Box<dyn Strategy>,
+    W: Write,
+{
+    let num_players = config.num_players;
+    game::check_players(num_players, rules).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    if config.format == ExportFormat::Csv {
+        write_csv_header(out)?;
+    }
+
+    let log = Rc::new(RefCell::new(Log::default()));
+    let mut seeds = StdRng::seed_from_u64(seed);
+    let mut written = 0;
+    for match_index in 0..config.num_matches {
+        let match_seed: u64 = seeds.gen();
+        let mut strategies: Vec<Box<dyn Strategy>> = (0..num_players)
+            .map(|seat| {
+                Box::new(Recorder {
+                    inner: make_strategy(seat, match_seed),
+                    seat,
+                    log: log.clone(),
+                    round_start: 0,
+                }) as Box<dyn Strategy>
+            })
+            .collect();
+
+        let mut game_match = Match::new(num_players, rules, config.match_config, match_seed)
+            .expect("Invalid game state: the player count was already checked");
+        while !game_match.is_over() {
+            {
+                let mut log = log.borrow_mut();
+                log.match_index = match_index;
+                log.round = game_match.rounds.len();
+                log.match_scores = game_match.scores.clone();
+            }
+            let mut state = game_match.next_round();
+            game::play_round(&mut state, &mut strategies);
+            game_match.record_round(&state);
+
+            // Everything's got its outcome now, so nothing needs to be kept
+            let samples = std::mem::take(&mut log.borrow_mut().samples);
+            for sample in samples.iter() {
+                write_sample(out, config.format, sample)?;
+            }
+            written += samples.len();
+        }
+    }
+    out.flush()?;
+    Ok(written)
+}
+
+fn write_csv_header<W: Write>(out: &mut W) -> io::Result<()> {
+    write!(out, "match_index,round,player,outcome")?;
+    for i in 0..features::FEATURE_LEN {
+        write!(out, ",f{}", i)?;
+    }
+    for i in 0..features::ACTION_LEN {
+        write!(out, ",a{}", i)?;
+    }
+    writeln!(out)
+}
+
+fn write_sample<W: Write>(out: &mut W, format: ExportFormat, sample: &Sample) -> io::Result<()> {
+    match format {
+        ExportFormat::Csv => {
+            write!(out, "{},{},{},{}", sample.match_index, sample.round, sample.player, sample.outcome)?;
+            for value in sample.features.iter().chain(sample.action.iter()) {
+                write!(out, ",{}", value)?;
+            }
+            writeln!(out)
+        },
+        #[cfg(feature = "json")]
+        ExportFormat::JsonLines => {
+            serde_json::to_writer(&mut *out, sample)?;
+            writeln!(out)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gin;
+    use crate::greedy::GreedyStrategy;
+
+    // Rows of numbers, after checking the header
+    fn generate_csv(rules: &RuleSet, config: SelfPlayConfig) -> (usize, Vec<Vec<f64>>) {
+        let mut out = vec![];
+        let written = generate(rules, config, 3, |_, _| Box::new(GreedyStrategy), &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let mut lines = text.lines();
+        let header: Vec<&str> = lines.next().unwrap().split(',').collect();
+        assert_eq!(header.len(), 4 + features::FEATURE_LEN + features::ACTION_LEN);
+        assert_eq!(header[..4], ["match_index", "round", "player", "outcome"]);
+
+        let rows: Vec<Vec<f64>> = lines
+            .map(|line| line.split(',').map(|value| value.parse().unwrap()).collect())
+            .collect();
+        assert!(rows.iter().all(|row| row.len() == header.len()));
+        (written, rows)
+    }
+
+    // Which of the action kinds (stock, top discard, dig, play, discard,
+    // knock) a row is
+    fn action_kind(row: &[f64]) -> usize {
+        let start = 4 + features::FEATURE_LEN;
+        (0..6).find(|kind| row[start + kind] == 1.0).unwrap()
+    }
+
+    #[test]
+    fn one_match_writes_a_row_per_decision() {
+        let config = SelfPlayConfig { num_matches: 1, ..SelfPlayConfig::default() };
+        let (written, rows) = generate_csv(&RuleSet::default(), config);
+        assert!(written > 0);
+        assert_eq!(written, rows.len());
+        assert!(rows.iter().all(|row| row[0] == 0.0));
+        assert!(rows.iter().any(|row| row[3] != 0.0));
+        // Both players get an outcome every round
+        for player in 0..2 {
+            assert!(rows.iter().any(|row| row[2] == player as f64 && row[3] != 0.0));
+        }
+    }
+
+    #[test]
+    fn knocks_and_discards_are_recorded_once_each() {
+        let config = SelfPlayConfig {
+            num_matches: 2,
+            match_config: MatchConfig { max_rounds: 3, ..MatchConfig::default() },
+            ..SelfPlayConfig::default()
+        };
+        let (_, rows) = generate_csv(&gin::rules(), config);
+        let rounds: Vec<(f64, f64)> = rows.iter().map(|row| (row[0], row[1])).collect();
+        for round in rounds.iter() {
+            let kinds: Vec<usize> = rows
+                .iter()
+                .filter(|row| (row[0], row[1]) == *round)
+                .map(|row| action_kind(row))
+                .collect();
+            let draws = kinds.iter().filter(|kind| **kind <= 2).count();
+            let ends = kinds.iter().filter(|kind| **kind >= 4).count();
+            // Every Gin turn ends in a discard or a knock
+            assert_eq!(draws, ends);
+            assert!(kinds.iter().filter(|kind| **kind == 5).count() <= 1);
+        }
+    }
+}
@@ -0,0 +1,438 @@
+use std::io::{self, Write};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::export::Exporter;
+use crate::game::{self, GameState, RoundOutcome, Strategy};
+use crate::game_match::{Match, MatchConfig};
+use crate::greedy::GreedyStrategy;
+use crate::heuristic::HeuristicStrategy;
+use crate::ismcts::{IsmctsBot, IsmctsConfig};
+use crate::monte_carlo::{MonteCarloBot, MonteCarloConfig};
+use crate::rules::RuleSet;
+use crate::stats::{self, RunningStats};
+
+// Playing lots of matches between strategies and adding up how they did.
+// Everything's seeded so a run can be repeated exactly.
+//
+// Differences between bots tend to be small next to the luck of the deal, so
+// reports come with confidence intervals. A paired comparison plays each seed
+// twice, once with each of the two strategies being compared, so both get
+// exactly the same cards and most of the luck cancels out. It can also stop
+// as soon as the difference is clearly real.
+
+pub struct SimulationReport {
+    pub matches: usize,
+    pub rounds: usize,
+    // Rounds stopped by the turn cap rather than finishing properly. If this
+    // isn't tiny the strategies are probably stuck in a loop
+    pub turn_capped_rounds: usize,
+    // Per seat. A shared win is split between the players sharing it
+    pub wins: Vec<f64>,
+    pub total_scores: Vec<i64>,
+    pub score_stats: Vec<RunningStats>,
+}
+
+impl SimulationReport {
+    pub fn new(num_players: usize) -> Self {
+        SimulationReport {
+            matches: 0,
+            rounds: 0,
+            turn_capped_rounds: 0,
+            wins: vec![0.0; num_players],
+            total_scores: vec![0; num_players],
+            score_stats: vec![RunningStats::new(); num_players],
+        }
+    }
+
+    pub fn add_match(&mut self, game_match: &Match) {
+        self.matches += 1;
+        self.rounds += game_match.rounds.len();
+        self.turn_capped_rounds += game_match
+            .rounds
+            .iter()
+            .filter(|round| round.outcome == RoundOutcome::TurnCap)
+            .count();
+        let winners = game_match.winners();
+        for winner in winners.iter() {
+            self.wins[*winner] += 1.0 / winners.len() as f64;
+        }
+        for (seat, score) in game_match.scores.iter().enumerate() {
+            self.total_scores[seat] += *score as i64;
+            self.score_stats[seat].add(*score as f64);
+        }
+    }
+
+    pub fn win_rate(&self, seat: usize) -> f64 {
+        self.wins[seat] / self.matches as f64
+    }
+
+    // Final match score, on average
+    pub fn mean_score(&self, seat: usize) -> f64 {
+        self.total_scores[seat] as f64 / self.matches as f64
+    }
+
+    // Confidence is two sided, e.g. 0.95
+    pub fn win_rate_interval(&self, seat: usize, confidence: f64) -> (f64, f64) {
+        stats::wilson_interval(self.wins[seat], self.matches, confidence)
+    }
+
+    pub fn mean_score_interval(&self, seat: usize, confidence: f64) -> (f64, f64) {
+        self.score_stats[seat].interval(confidence)
+    }
+}
+
+// Bots that can be asked for by name, with their default settings. For
+// running simulations from outside rust (the server and the python bindings)
+pub const STRATEGY_NAMES: [&str; 4] = ["greedy", "heuristic", "monte_carlo", "ismcts"];
+
+pub fn strategy_by_name(name: &str, seat: usize, seed: u64) -> Result<Box<dyn Strategy>, String> {
+    // Different seats shouldn't sample the same worlds
+    let seed = seed.wrapping_add(seat as u64);
+    let strategy: Box<dyn Strategy> = match name {
+        "greedy" => Box::new(GreedyStrategy),
+        "heuristic" => Box::new(HeuristicStrategy::default()),
+        "monte_carlo" => Box::new(MonteCarloBot::new(MonteCarloConfig::default(), seed)),
+        "ismcts" => Box::new(IsmctsBot::new(IsmctsConfig::default(), seed)),
+        _ => {
+            return Err(format!(
+                "Unknown strategy \"{}\", expected one of {}",
+                name,
+                STRATEGY_NAMES.join(", "),
+            ))
+        },
+    };
+    Ok(strategy)
+}
+
+// Plays `num_matches` matches. `make_strategies` gets each match's seed and
+// returns a fresh strategy for every seat. Errors if the rules can't be dealt
+// to that many players
+pub fn simulate_matches<F>(
+    num_players: usize,
+    rules: &RuleSet,
+    config: MatchConfig,
+    num_matches: usize,
+    seed: u64,
+    make_strategies: F,
+) -> Result<SimulationReport, String>
+where
+    F: FnMut(u64) -> Vec<Box<dyn Strategy>>,
+{
+    simulate_matches_with(num_players, rules, config, num_matches, seed, make_strategies, |_, _, _| Ok(()))
+        .map_err(|e| e.to_string())
+}
+
+// Same, writing every round out as it finishes
+pub fn simulate_matches_exported<F, W>(
+    num_players: usize,
+    rules: &RuleSet,
+    config: MatchConfig,
+    num_matches: usize,
+    seed: u64,
+    make_strategies: F,
+    exporter: &mut Exporter<W>,
+) -> io::Result<SimulationReport>
+where
+    F: FnMut(u64) -> Vec<Box<dyn Strategy>>,
+    W: Write,
+{
+    let report = simulate_matches_with(
+        num_players,
+        rules,
+        config,
+        num_matches,
+        seed,
+        make_strategies,
+        |match_index, round, state| exporter.write_round(match_index, round, state),
+    )?;
+    exporter.flush()?;
+    Ok(report)
+}
+
+// Same again, handing every finished round to `on_round` along with the
+// match index and the round number. Too many players is an InvalidInput error
+pub fn simulate_matches_with<F, R>(
+    num_players: usize,
+    rules: &RuleSet,
+    config: MatchConfig,
+    num_matches: usize,
+    seed: u64,
+    mut make_strategies: F,
+    mut on_round: R,
+) -> io::Result<SimulationReport>
+where
+    F: FnMut(u64) -> Vec<Box<dyn Strategy>>,
+    R: FnMut(usize, usize, &GameState) -> io::Result<()>,
+{
+    game::check_players(num_players, rules).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut seeds = StdRng::seed_from_u64(seed);
+    let mut report = SimulationReport::new(num_players);
+    for match_index in 0..num_matches {
+        let match_seed: u64 = seeds.gen();
+        let mut strategies = make_strategies(match_seed);
+        let mut game_match = Match::new(num_players, rules, config, match_seed)
+            .expect("Invalid game state: the player count was already checked");
+        while !game_match.is_over() {
+            let mut state = game_match.next_round();
+            game::play_round(&mut state, &mut strategies);
+            on_round(match_index, game_match.rounds.len(), &state)?;
+            game_match.record_round(&state);
+        }
+        report.add_match(&game_match);
+    }
+    Ok(report)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Wins,
+    Score,
+}
+
+#[derive(Clone, Copy)]
+pub struct PairedConfig {
+    // Stops here if the difference never gets significant
+    pub max_pairs: usize,
+    pub confidence: f64,
+    // Whether to stop as soon as the difference is significant
+    pub sequential: bool,
+    // How often to check, in pairs
+    pub check_every: usize,
+    // Which difference the sequential test looks at
+    pub metric: Metric,
+}
+
+impl Default for PairedConfig {
+    fn default() -> Self {
+        PairedConfig {
+            max_pairs: 1000,
+            confidence: 0.95,
+            sequential: true,
+            check_every: 50,
+            metric: Metric::Wins,
+        }
+    }
+}
+
+pub struct PairedReport {
+    pub pairs: usize,
+    pub stopped_early: bool,
+    // Seat 0's results with each strategy sitting there
+    pub challenger: SimulationReport,
+    pub baseline: SimulationReport,
+    // Challenger minus baseline, one value per seed. Wins are 0 to 1 per
+    // match so these are -1 to 1
+    pub win_difference: RunningStats,
+    pub score_difference: RunningStats,
+    pub confidence: f64,
+}
+
+impl PairedReport {
+    // Interval for how much more often the challenger wins
+    pub fn win_difference_interval(&self) -> (f64, f64) {
+        self.win_difference.interval(self.confidence)
+    }
+
+    pub fn score_difference_interval(&self) -> (f64, f64) {
+        self.score_difference.interval(self.confidence)
+    }
+
+    // Whether the interval leaves out 0
+    pub fn is_significant(&self, metric: Metric) -> bool {
+        let (low, high) = match metric {
+            Metric::Wins => self.win_difference_interval(),
+            Metric::Score => self.score_difference_interval(),
+        };
+        low > 0.0 || high < 0.0
+    }
+}
+
+// Compares two strategies in seat 0 against the same opponents. Each seed gets
+// played once with `make_challenger`'s table and once with `make_baseline`'s,
+// and both should return seat 0's strategy first followed by the same
+// opponents.
+//
+// With config.sequential the test gets looked at every `check_every` pairs.
+// Looking more than once makes it easier to find a difference that isn't
+// there, so each look has to pass the confidence level divided by the number
+// of looks. That's stricter than it needs to be but simple, and it means
+// stopping early can be trusted as much as a fixed-size run. Errors if the
+// rules can't be dealt to that many players
+pub fn compare_paired<C, B>(
+    num_players: usize,
+    rules: &RuleSet,
+    config: MatchConfig,
+    paired: PairedConfig,
+    seed: u64,
+    mut make_challenger: C,
+    mut make_baseline: B,
+) -> Result<PairedReport, String>
+where
+    C: FnMut(u64) -> Vec<Box<dyn Strategy>>,
+    B: FnMut(u64) -> Vec<Box<dyn Strategy>>,
+{
+    game::check_players(num_players, rules)?;
+    let check_every = paired.check_every.max(1);
+    let num_looks = paired.max_pairs.div_ceil(check_every).max(1);
+    let look_confidence = 1.0 - (1.0 - paired.confidence) / num_looks as f64;
+    let look_z = stats::z_for_confidence(look_confidence);
+
+    let mut seeds = StdRng::seed_from_u64(seed);
+    let mut report = PairedReport {
+        pairs: 0,
+        stopped_early: false,
+        challenger: SimulationReport::new(num_players),
+        baseline: SimulationReport::new(num_players),
+        win_difference: RunningStats::new(),
+        score_difference: RunningStats::new(),
+        confidence: paired.confidence,
+    };
+
+    while report.pairs < paired.max_pairs {
+        let match_seed: u64 = seeds.gen();
+        let (challenger_wins, challenger_score) =
+            play_for_seat_0(num_players, rules, config, match_seed, &mut make_challenger, &mut report.challenger);
+        let (baseline_wins, baseline_score) =
+            play_for_seat_0(num_players, rules, config, match_seed, &mut make_baseline, &mut report.baseline);
+
+        report.pairs += 1;
+        report.win_difference.add(challenger_wins - baseline_wins);
+        report.score_difference.add((challenger_score - baseline_score) as f64);
+
+        if paired.sequential && report.pairs.is_multiple_of(check_every) && report.pairs < paired.max_pairs {
+            let difference = match paired.metric {
+                Metric::Wins => &report.win_difference,
+                Metric::Score => &report.score_difference,
+            };
+            if difference.z_score().abs() > look_z {
+                report.stopped_early = true;
+                break;
+            }
+        }
+    }
+    Ok(report)
+}
+
+// Seat 0's share of the win and final score
+fn play_for_seat_0<F>(
+    num_players: usize,
+    rules: &RuleSet,
+    config: MatchConfig,
+    match_seed: u64,
+    make_strategies: &mut F,
+    report: &mut SimulationReport,
+) -> (f64, i32)
+where
+    F: FnMut(u64) -> Vec<Box<dyn Strategy>>,
+{
+    let mut strategies = make_strategies(match_seed);
+    let mut game_match = Match::new(num_players, rules, config, match_seed)
+        .expect("Invalid game state: the player count was already checked");
+    let winners = game_match.play(&mut strategies);
+    report.add_match(&game_match);
+
+    let wins = if winners.contains(&0) { 1.0 / winners.len() as f64 } else { 0.0 };
+    (wins, game_match.scores[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::Card;
+    use crate::game::{Draw, PlayerView};
+    use crate::score::Play;
+
+    // Never lays anything down, so it's easy to beat
+    struct NeverMelds;
+
+    impl Strategy for NeverMelds {
+        fn choose_draw(&mut self, view: &PlayerView) -> Draw {
+            if view.legal_draws().iter().any(|draw| matches!(draw, Draw::Stock)) {
+                Draw::Stock
+            } else {
+                Draw::TopDiscard
+            }
+        }
+
+        fn choose_play(&mut self, _view: &PlayerView) -> Option<Play> {
+            None
+        }
+
+        fn choose_discard(&mut self, view: &PlayerView) -> Card {
+            view.hand().as_ordered_list()[0]
+        }
+    }
+
+    fn one_round_matches() -> MatchConfig {
+        MatchConfig { max_rounds: 1, ..MatchConfig::default() }
+    }
+
+    fn greedy_table(_: u64) -> Vec<Box<dyn Strategy>> {
+        vec![Box::new(GreedyStrategy), Box::new(GreedyStrategy)]
+    }
+
+    #[test]
+    fn identical_strategies_make_no_difference() {
+        let paired = PairedConfig { max_pairs: 6, check_every: 2, ..PairedConfig::default() };
+        let report = compare_paired(2, &RuleSet::default(), one_round_matches(), paired, 1, greedy_table, greedy_table).unwrap();
+        assert_eq!(report.pairs, 6);
+        assert!(!report.stopped_early);
+        assert_eq!(report.win_difference.mean, 0.0);
+        assert_eq!(report.score_difference.mean, 0.0);
+        assert!(!report.is_significant(Metric::Wins));
+        assert!(!report.is_significant(Metric::Score));
+    }
+
+    #[test]
+    fn a_clearly_better_challenger_stops_early() {
+        let paired = PairedConfig {
+            max_pairs: 100,
+            check_every: 5,
+            metric: Metric::Score,
+            ..PairedConfig::default()
+        };
+        let report = compare_paired(
+            2,
+            &RuleSet::default(),
+            one_round_matches(),
+            paired,
+            1,
+            |_| vec![Box::new(GreedyStrategy), Box::new(NeverMelds)],
+            |_| vec![Box::new(NeverMelds), Box::new(NeverMelds)],
+        )
+        .unwrap();
+        assert!(report.stopped_early);
+        assert!(report.pairs < 100);
+        assert_eq!(report.pairs % 5, 0);
+        assert!(report.score_difference.mean > 0.0);
+        assert_eq!(report.challenger.matches, report.pairs);
+        assert_eq!(report.baseline.matches, report.pairs);
+    }
+
+    #[test]
+    fn fixed_size_runs_play_max_pairs() {
+        let paired = PairedConfig { max_pairs: 7, sequential: false, check_every: 1, ..PairedConfig::default() };
+        let report = compare_paired(
+            2,
+            &RuleSet::default(),
+            one_round_matches(),
+            paired,
+            1,
+            |_| vec![Box::new(GreedyStrategy), Box::new(NeverMelds)],
+            |_| vec![Box::new(NeverMelds), Box::new(NeverMelds)],
+        )
+        .unwrap();
+        assert_eq!(report.pairs, 7);
+        assert!(!report.stopped_early);
+        assert_eq!(report.challenger.matches, 7);
+    }
+
+    #[test]
+    fn undealable_tables_are_an_error() {
+        let paired = PairedConfig { max_pairs: 1, ..PairedConfig::default() };
+        let table = |_| -> Vec<Box<dyn Strategy>> { vec![] };
+        assert!(compare_paired(1, &RuleSet::default(), one_round_matches(), paired, 1, table, table).is_err());
+    }
+}
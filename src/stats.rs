@@ -0,0 +1,188 @@
+// Just enough statistics to tell whether a difference between two bots is
+// real: running means with standard errors, confidence intervals, and the
+// normal distribution to get them from.
+
+// Mean and variance without keeping every value (Welford's method)
+#[derive(Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RunningStats {
+    pub count: usize,
+    pub mean: f64,
+    // Sum of squared differences from the mean
+    m2: f64,
+}
+
+impl RunningStats {
+    pub fn new() -> Self {
+        RunningStats::default()
+    }
+
+    pub fn add(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    // Sample variance
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 { 0.0 } else { self.m2 / (self.count - 1) as f64 }
+    }
+
+    pub fn std_error(&self) -> f64 {
+        if self.count == 0 { 0.0 } else { (self.variance() / self.count as f64).sqrt() }
+    }
+
+    // Normal approximation, fine once there's more than a few dozen values
+    pub fn interval(&self, confidence: f64) -> (f64, f64) {
+        let margin = z_for_confidence(confidence) * self.std_error();
+        (self.mean - margin, self.mean + margin)
+    }
+
+    // How many standard errors the mean is away from 0. Values that are all
+    // the same (and not 0) are as significant as it gets, so that's infinite.
+    // 0 until there are at least two values to go on
+    pub fn z_score(&self) -> f64 {
+        let std_error = self.std_error();
+        if self.count < 2 || self.mean == 0.0 {
+            0.0
+        } else if std_error == 0.0 {
+            self.mean.signum() * f64::INFINITY
+        } else {
+            self.mean / std_error
+        }
+    }
+}
+
+// Wilson score interval for a proportion. Behaves much better than the plain
+// normal one near 0 and 1. Successes can be fractional (shared wins)
+pub fn wilson_interval(successes: f64, trials: usize, confidence: f64) -> (f64, f64) {
+    if trials == 0 {
+        return (0.0, 1.0);
+    }
+    let n = trials as f64;
+    let p = successes / n;
+    let z = z_for_confidence(confidence);
+    let z2 = z * z;
+
+    let centre = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+    let margin = z / (1.0 + z2 / n) * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+    ((centre - margin).max(0.0), (centre + margin).min(1.0))
+}
+
+// Two sided: 0.95 gives about 1.96
+pub fn z_for_confidence(confidence: f64) -> f64 {
+    normal_quantile(0.5 + confidence / 2.0)
+}
+
+pub fn normal_cdf(z: f64) -> f64 {
+    0.5 * (1.0 + erf(z / std::f64::consts::SQRT_2))
+}
+
+// Inverse of normal_cdf, by bisection
+pub fn normal_quantile(p: f64) -> f64 {
+    if p <= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if p >= 1.0 {
+        return f64::INFINITY;
+    }
+    let (mut low, mut high) = (-40.0, 40.0);
+    for _ in 0..100 {
+        let middle = (low + high) / 2.0;
+        if normal_cdf(middle) < p {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    (low + high) / 2.0
+}
+
+// Abramowitz and Stegun 7.1.26, good to about 1e-7
+fn erf(x: f64) -> f64 {
+    let sign = if x < 0.0 { -1.0 } else { 1.0 };
+    let x = x.abs();
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let polynomial = t * (0.254829592
+        + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    sign * (1.0 - polynomial * (-x * x).exp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats_of(values: &[f64]) -> RunningStats {
+        let mut stats = RunningStats::new();
+        for value in values.iter() {
+            stats.add(*value);
+        }
+        stats
+    }
+
+    #[test]
+    fn mean_and_variance() {
+        let stats = stats_of(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_eq!(stats.mean, 5.0);
+        assert!((stats.variance() - 32.0 / 7.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn identical_nonzero_values_are_infinitely_significant() {
+        assert_eq!(stats_of(&[1.0, 1.0, 1.0]).z_score(), f64::INFINITY);
+        assert_eq!(stats_of(&[-0.5, -0.5]).z_score(), f64::NEG_INFINITY);
+        assert!(stats_of(&[1.0, 1.0]).z_score().abs() > z_for_confidence(0.999));
+    }
+
+    #[test]
+    fn no_evidence_gives_a_z_score_of_0() {
+        assert_eq!(RunningStats::new().z_score(), 0.0);
+        assert_eq!(stats_of(&[3.0]).z_score(), 0.0);
+        assert_eq!(stats_of(&[0.0, 0.0, 0.0]).z_score(), 0.0);
+    }
+
+    #[test]
+    fn z_score_is_the_mean_over_its_standard_error() {
+        let stats = stats_of(&[1.0, 2.0, 3.0, 4.0]);
+        assert!((stats.z_score() - stats.mean / stats.std_error()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn wilson_interval_with_no_trials_is_everything() {
+        assert_eq!(wilson_interval(0.0, 0, 0.95), (0.0, 1.0));
+    }
+
+    #[test]
+    fn wilson_interval_matches_the_textbook_numbers() {
+        let (low, high) = wilson_interval(50.0, 100, 0.95);
+        assert!((low - 0.4038).abs() < 1e-3);
+        assert!((high - 0.5962).abs() < 1e-3);
+    }
+
+    #[test]
+    fn wilson_interval_stays_inside_0_to_1() {
+        let (low, high) = wilson_interval(0.0, 10, 0.95);
+        assert!(low.abs() < 1e-12);
+        assert!(high > 0.0 && high < 0.5);
+        let (low, high) = wilson_interval(10.0, 10, 0.95);
+        assert!(low > 0.5 && low < 1.0);
+        assert!((high - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn normal_quantile_known_values() {
+        assert!(normal_quantile(0.5).abs() < 1e-6);
+        assert!((normal_quantile(0.975) - 1.959964).abs() < 1e-5);
+        assert!((normal_quantile(0.025) + 1.959964).abs() < 1e-5);
+        assert_eq!(normal_quantile(0.0), f64::NEG_INFINITY);
+        assert_eq!(normal_quantile(1.0), f64::INFINITY);
+    }
+
+    #[test]
+    fn normal_quantile_inverts_the_cdf() {
+        for z in [-3.0, -1.2, 0.3, 2.5] {
+            assert!((normal_quantile(normal_cdf(z)) - z).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,470 @@
+use std::io::{self, Write};
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::export::csv_field;
+use crate::game::{self, Strategy};
+use crate::game_match::{Match, MatchConfig};
+use crate::rules::RuleSet;
+
+// Pitting a bunch of strategies against each other and rating them.
+//
+// Entrants get registered with a name and a way of making a fresh strategy.
+// Tables can be any size: round robin plays every possible table once (with
+// the seats rotated between matches), Swiss pairs entrants with similar
+// ratings round by round.
+//
+// Ratings are Elo-style, fitted to every match at once (Bradley-Terry) rather
+// than updated one match at a time, so the order matches were played in
+// doesn't matter. A table of more than two counts as a game between every
+// pair at it. Everyone also gets one imaginary draw against a 1500 player,
+// which keeps an entrant that's won (or lost) everything from going off to
+// infinity.
+
+const BASE_RATING: f64 = 1500.0;
+const MAX_FIT_ITERATIONS: usize = 1000;
+
+pub struct Entrant {
+    pub name: String,
+    // Makes a fresh strategy for a match, given the seat and the match seed
+    pub make: Box<dyn Fn(usize, u64) -> Box<dyn Strategy>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Pairing {
+    // Every possible table of `table_size` entrants
+    RoundRobin,
+    // Each round, entrants are sorted by rating and seated in that order.
+    // Whoever doesn't fit at a table sits the round out, taking turns
+    Swiss { rounds: usize },
+}
+
+#[derive(Clone, Copy)]
+pub struct TournamentConfig {
+    pub pairing: Pairing,
+    pub table_size: usize,
+    // Matches each table plays, moving everyone one seat along each time
+    pub matches_per_table: usize,
+    pub match_config: MatchConfig,
+}
+
+impl Default for TournamentConfig {
+    fn default() -> Self {
+        TournamentConfig {
+            pairing: Pairing::RoundRobin,
+            table_size: 2,
+            matches_per_table: 2,
+            match_config: MatchConfig::default(),
+        }
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MatchResult {
+    // Swiss round, always 0 for round robin
+    pub round: usize,
+    pub seed: u64,
+    // Entrant in each seat
+    pub entrants: Vec<usize>,
+    // Final match scores, per seat
+    pub scores: Vec<i32>,
+    // Seats
+    pub winners: Vec<usize>,
+    pub rounds_played: usize,
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Rating {
+    pub entrant: usize,
+    pub name: String,
+    pub rating: f64,
+    // Standard error of the rating. Games at the same table aren't really
+    // independent so treat this as a bit optimistic with more than 2 seats
+    pub deviation: f64,
+    pub matches: usize,
+    // A shared win is split between the players sharing it
+    pub wins: f64,
+    pub mean_score: f64,
+}
+
+pub struct Tournament {
+    pub rules: RuleSet,
+    pub config: TournamentConfig,
+    pub entrants: Vec<Entrant>,
+    pub results: Vec<MatchResult>,
+    rng: StdRng,
+}
+
+impl Tournament {
+    pub fn new(rules: &RuleSet, config: TournamentConfig, seed: u64) -> Self {
+        Tournament {
+            rules: rules.clone(),
+            config,
+            entrants: vec![],
+            results: vec![],
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    // Returns the entrant's index
+    pub fn register<F>(&mut self, name: &str, make: F) -> usize
+    where
+        F: Fn(usize, u64) -> Box<dyn Strategy> + 'static,
+    {
+        self.entrants.push(Entrant { name: name.to_string(), make: Box::new(make) });
+        self.entrants.len() - 1
+    }
+
+    // Errors if there aren't enough entrants for a table, or the rules can't
+    // be dealt to a table that size
+    pub fn run(&mut self) -> Result<(), String> {
+        if self.entrants.len() < self.config.table_size {
+            return Err(format!("Not enough entrants for a table of {}", self.config.table_size));
+        }
+        game::check_players(self.config.table_size, &self.rules)?;
+
+        match self.config.pairing {
+            Pairing::RoundRobin => {
+                for table in combinations(self.entrants.len(), self.config.table_size) {
+                    self.play_table(0, &table);
+                }
+            },
+            Pairing::Swiss { rounds } => {
+                let mut byes = vec![0; self.entrants.len()];
+                for round in 0..rounds {
+                    for table in self.swiss_tables(&mut byes) {
+                        self.play_table(round, &table);
+                    }
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn play_table(&mut self, round: usize, table: &[usize]) {
+        for rotation in 0..self.config.matches_per_table {
+            let mut seats = table.to_vec();
+            seats.rotate_left(rotation % table.len());
+            let seed: u64 = self.rng.gen();
+
+            let mut strategies: Vec<Box<dyn Strategy>> = seats
+                .iter()
+                .enumerate()
+                .map(|(seat, entrant)| (self.entrants[*entrant].make)(seat, seed))
+                .collect();
+            let mut game_match = Match::new(seats.len(), &self.rules, self.config.match_config, seed)
+                .expect("Invalid game state: the table size was already checked");
+            let winners = game_match.play(&mut strategies);
+
+            self.results.push(MatchResult {
+                round,
+                seed,
+                entrants: seats,
+                scores: game_match.scores.clone(),
+                winners,
+                rounds_played: game_match.rounds.len(),
+            });
+        }
+    }
+
+    // Best rated first. Whoever's had the fewest byes and is rated lowest
+    // sits out when the numbers don't work out
+    fn swiss_tables(&mut self, byes: &mut [usize]) -> Vec<Vec<usize>> {
+        let mut order: Vec<usize> = if self.results.is_empty() {
+            let mut order: Vec<usize> = (0..self.entrants.len()).collect();
+            order.shuffle(&mut self.rng);
+            order
+        } else {
+            self.ratings().iter().map(|r| r.entrant).collect()
+        };
+
+        let num_byes = order.len() % self.config.table_size;
+        for _ in 0..num_byes {
+            let fewest = order.iter().map(|e| byes[*e]).min().unwrap();
+            let position = order.iter().rposition(|e| byes[*e] == fewest).unwrap();
+            byes[order.remove(position)] += 1;
+        }
+
+        order.chunks(self.config.table_size).map(|table| table.to_vec()).collect()
+    }
+
+    // Best first
+    pub fn ratings(&self) -> Vec<Rating> {
+        let n = self.entrants.len();
+        // games[i][j] is how many times i and j were at the same table,
+        // score[i] how many of those i came out ahead in (half for a tie)
+        let mut games = vec![vec![0.0; n]; n];
+        let mut score = vec![0.5; n];
+        let mut matches = vec![0; n];
+        let mut wins = vec![0.0; n];
+        let mut total_scores = vec![0i64; n];
+
+        for result in self.results.iter() {
+            for (seat, entrant) in result.entrants.iter().enumerate() {
+                matches[*entrant] += 1;
+                total_scores[*entrant] += result.scores[seat] as i64;
+                if result.winners.contains(&seat) {
+                    wins[*entrant] += 1.0 / result.winners.len() as f64;
+                }
+            }
+            for a in 0..result.entrants.len() {
+                for b in (a + 1)..result.entrants.len() {
+                    let (i, j) = (result.entrants[a], result.entrants[b]);
+                    games[i][j] += 1.0;
+                    games[j][i] += 1.0;
+                    match finish_order(result, a).cmp(&finish_order(result, b)) {
+                        std::cmp::Ordering::Greater => score[i] += 1.0,
+                        std::cmp::Ordering::Less => score[j] += 1.0,
+                        std::cmp::Ordering::Equal => {
+                            score[i] += 0.5;
+                            score[j] += 0.5;
+                        },
+                    }
+                }
+            }
+        }
+
+        // Hunter's MM algorithm. Strengths are relative to the imaginary
+        // 1500 player, who has strength 1
+        let mut strength: Vec<f64> = vec![1.0; n];
+        for _ in 0..MAX_FIT_ITERATIONS {
+            let mut largest_change: f64 = 0.0;
+            for i in 0..n {
+                let mut denominator = 1.0 / (strength[i] + 1.0);
+                for j in 0..n {
+                    if games[i][j] > 0.0 {
+                        denominator += games[i][j] / (strength[i] + strength[j]);
+                    }
+                }
+                let updated = score[i] / denominator;
+                largest_change = largest_change.max((updated / strength[i]).ln().abs());
+                strength[i] = updated;
+            }
+            if largest_change < 1e-9 {
+                break;
+            }
+        }
+
+        let elo_scale = 400.0 / 10f64.ln();
+        let mut ratings: Vec<Rating> = (0..n)
+            .map(|i| {
+                let expected = |other: f64| strength[i] * other / (strength[i] + other).powi(2);
+                let mut information = expected(1.0);
+                for j in 0..n {
+                    information += games[i][j] * expected(strength[j]);
+                }
+                Rating {
+                    entrant: i,
+                    name: self.entrants[i].name.clone(),
+                    rating: BASE_RATING + elo_scale * strength[i].ln(),
+                    deviation: elo_scale / information.sqrt(),
+                    matches: matches[i],
+                    wins: wins[i],
+                    mean_score: if matches[i] == 0 { 0.0 } else { total_scores[i] as f64 / matches[i] as f64 },
+                }
+            })
+            .collect();
+        ratings.sort_by(|a, b| b.rating.partial_cmp(&a.rating).unwrap());
+        ratings
+    }
+
+    // Plain text, for printing
+    pub fn leaderboard(&self) -> String {
+        let ratings = self.ratings();
+        let name_width = ratings.iter().map(|r| r.name.len()).max().unwrap_or(0).max(4);
+
+        let mut table = format!(
+            "{:>4}  {:<name_width$}  {:>6}  {:>5}  {:>7}  {:>7}  {:>6}  {:>10}\n",
+            "Rank", "Name", "Rating", "95%", "Matches", "Wins", "Win %", "Mean score",
+        );
+        for (rank, r) in ratings.iter().enumerate() {
+            let win_rate = if r.matches == 0 { 0.0 } else { 100.0 * r.wins / r.matches as f64 };
+            table += &format!(
+                "{:>4}  {:<name_width$}  {:>6.0}  ±{:>4.0}  {:>7}  {:>7.1}  {:>6.1}  {:>10.1}\n",
+                rank + 1,
+                r.name,
+                r.rating,
+                1.96 * r.deviation,
+                r.matches,
+                r.wins,
+                win_rate,
+                r.mean_score,
+            );
+        }
+        table
+    }
+
+    // One row per match: round, seed, rounds played, then entrant name,
+    // score and whether they won for each seat
+    pub fn write_csv<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "round,seed,rounds_played")?;
+        for seat in 0..self.config.table_size {
+            write!(out, ",entrant_{0},score_{0},won_{0}", seat)?;
+        }
+        writeln!(out)?;
+
+        for result in self.results.iter() {
+            write!(out, "{},{},{}", result.round, result.seed, result.rounds_played)?;
+            for (seat, entrant) in result.entrants.iter().enumerate() {
+                let won = result.winners.contains(&seat);
+                write!(out, ",{},{},{}", csv_field(&self.entrants[*entrant].name), result.scores[seat], won)?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    // Everything: entrant names, every match and the ratings
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        #[derive(serde::Serialize)]
+        struct ResultsFile<'a> {
+            entrants: Vec<&'a str>,
+            results: &'a [MatchResult],
+            ratings: Vec<Rating>,
+        }
+
+        let file = ResultsFile {
+            entrants: self.entrants.iter().map(|e| e.name.as_str()).collect(),
+            results: &self.results,
+            ratings: self.ratings(),
+        };
+        serde_json::to_string_pretty(&file).unwrap()
+    }
+}
+
+// Winners ahead of everyone else, then by score
+fn finish_order(result: &MatchResult, seat: usize) -> (bool, i32) {
+    (result.winners.contains(&seat), result.scores[seat])
+}
+
+// Every way of picking k of 0..n, in order
+fn combinations(n: usize, k: usize) -> Vec<Vec<usize>> {
+    if k == 0 {
+        return vec![vec![]];
+    }
+    let mut all = vec![];
+    for last in (k - 1)..n {
+        for mut rest in combinations(last, k - 1) {
+            rest.push(last);
+            all.push(rest);
+        }
+    }
+    all
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::greedy::GreedyStrategy;
+
+    fn with_entrants(names: &[&str], config: TournamentConfig) -> Tournament {
+        let mut tournament = Tournament::new(&RuleSet::default(), config, 0);
+        for name in names.iter() {
+            tournament.register(name, |_, _| Box::new(GreedyStrategy));
+        }
+        tournament
+    }
+
+    // Seat 0 wins
+    fn result(entrants: Vec<usize>, scores: Vec<i32>) -> MatchResult {
+        MatchResult { round: 0, seed: 7, entrants, scores, winners: vec![0], rounds_played: 4 }
+    }
+
+    // 0 beats everyone, 1 beats 2, 2 never wins
+    fn pecking_order(times: usize) -> Tournament {
+        let mut tournament = with_entrants(&["top", "middle", "bottom"], TournamentConfig::default());
+        for _ in 0..times {
+            tournament.results.push(result(vec![0, 1], vec![510, 200]));
+            tournament.results.push(result(vec![0, 2], vec![505, 100]));
+            tournament.results.push(result(vec![1, 2], vec![500, 300]));
+        }
+        tournament
+    }
+
+    #[test]
+    fn ratings_follow_the_results() {
+        let ratings = pecking_order(3).ratings();
+        let order: Vec<usize> = ratings.iter().map(|r| r.entrant).collect();
+        assert_eq!(order, vec![0, 1, 2]);
+        assert!(ratings[0].rating > BASE_RATING);
+        assert!(ratings[2].rating < BASE_RATING);
+        assert_eq!(ratings[0].wins, 6.0);
+        assert_eq!(ratings[2].wins, 0.0);
+        assert_eq!(ratings[1].matches, 6);
+    }
+
+    #[test]
+    fn deviation_shrinks_with_more_matches() {
+        let few = pecking_order(2).ratings();
+        let many = pecking_order(20).ratings();
+        for entrant in 0..3 {
+            let deviation = |ratings: &[Rating]| ratings.iter().find(|r| r.entrant == entrant).unwrap().deviation;
+            assert!(deviation(&many) < deviation(&few));
+        }
+    }
+
+    #[test]
+    fn nobody_has_no_rating() {
+        let ratings = with_entrants(&["a", "b"], TournamentConfig::default()).ratings();
+        assert!(ratings.iter().all(|r| (r.rating - BASE_RATING).abs() < 1e-6));
+    }
+
+    #[test]
+    fn combinations_pick_every_table_once() {
+        assert_eq!(combinations(4, 2), vec![vec![0, 1], vec![0, 2], vec![1, 2], vec![0, 3], vec![1, 3], vec![2, 3]]);
+        assert_eq!(combinations(5, 3).len(), 10);
+        assert_eq!(combinations(3, 3), vec![vec![0, 1, 2]]);
+        assert!(combinations(2, 3).is_empty());
+    }
+
+    #[test]
+    fn swiss_byes_take_turns() {
+        let config = TournamentConfig { pairing: Pairing::Swiss { rounds: 5 }, ..TournamentConfig::default() };
+        let mut tournament = with_entrants(&["a", "b", "c", "d", "e"], config);
+        let mut byes = vec![0; 5];
+        for round in 0..5 {
+            let tables = tournament.swiss_tables(&mut byes);
+            assert_eq!(tables.len(), 2);
+            assert!(tables.iter().all(|table| table.len() == 2));
+            assert_eq!(byes.iter().sum::<usize>(), round + 1);
+            assert!(byes.iter().all(|b| *b <= 1));
+            // Rated order from now on
+            for table in tables.iter() {
+                tournament.results.push(result(table.clone(), vec![500, 0]));
+            }
+        }
+        assert_eq!(byes, vec![1; 5]);
+    }
+
+    #[test]
+    fn csv_has_a_column_group_per_seat() {
+        let mut tournament = with_entrants(&["Greedy, v2", "plain"], TournamentConfig::default());
+        tournament.results.push(result(vec![1, 0], vec![520, -15]));
+        let mut out = vec![];
+        tournament.write_csv(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "round,seed,rounds_played,entrant_0,score_0,won_0,entrant_1,score_1,won_1");
+        assert_eq!(lines[1], "0,7,4,plain,520,true,\"Greedy, v2\",-15,false");
+        assert_eq!(lines.len(), 2);
+    }
+
+    #[test]
+    fn round_robin_plays_every_table_each_way() {
+        let match_config = MatchConfig { max_rounds: 2, ..MatchConfig::default() };
+        let config = TournamentConfig { match_config, ..TournamentConfig::default() };
+        let mut tournament = with_entrants(&["a", "b", "c"], config);
+        tournament.run().unwrap();
+        assert_eq!(tournament.results.len(), 6);
+        assert!(tournament.results.iter().filter(|r| r.entrants == vec![0, 1]).count() == 1);
+        assert!(tournament.results.iter().filter(|r| r.entrants == vec![1, 0]).count() == 1);
+
+        let mut too_few = with_entrants(&["a"], config);
+        assert!(too_few.run().is_err());
+    }
+}
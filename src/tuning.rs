@@ -0,0 +1,259 @@
+use std::io;
+use std::path::PathBuf;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::game::{self, Strategy};
+use crate::game_match::{Match, MatchConfig};
+use crate::greedy::GreedyStrategy;
+use crate::heuristic::{HeuristicParams, HeuristicStrategy};
+use crate::monte_carlo::margin;
+use crate::rules::RuleSet;
+
+// Tuning HeuristicStrategy's parameters by self-play, with a simple evolution
+// strategy: each generation tries a population of random tweaks around the
+// current parameters, plays each of them against the opponent, and moves to
+// the average of the best few. The size of the tweaks shrinks as it goes.
+//
+// Everyone in a generation plays the same seeds, so they're compared on the
+// same cards. Results are still noisy, which is why it averages the best few
+// rather than jumping to the single best one.
+
+#[derive(Clone, Copy)]
+pub struct TunerConfig {
+    pub num_players: usize,
+    pub population: usize,
+    // How many of the best get averaged into the next parameters
+    pub parents: usize,
+    pub generations: usize,
+    // Per candidate. The candidate takes every seat in turn
+    pub matches: usize,
+    // Starting size of the tweaks, relative to each parameter's scale
+    pub step: f64,
+    // The step gets multiplied by this every generation
+    pub step_decay: f64,
+    pub match_config: MatchConfig,
+}
+
+impl Default for TunerConfig {
+    fn default() -> Self {
+        TunerConfig {
+            num_players: 2,
+            population: 16,
+            parents: 4,
+            generations: 20,
+            matches: 40,
+            step: 0.3,
+            step_decay: 0.9,
+            match_config: MatchConfig::default(),
+        }
+    }
+}
+
+pub struct Generation {
+    // What the tuner thinks is best after this generation
+    pub params: HeuristicParams,
+    // Mean of the parents' fitness
+    pub fitness: f64,
+    pub best_candidate: HeuristicParams,
+    pub best_fitness: f64,
+}
+
+pub struct Tuner {
+    pub rules: RuleSet,
+    pub config: TunerConfig,
+    pub params: HeuristicParams,
+    pub step: f64,
+    pub history: Vec<Generation>,
+    // Where to write the parameters after each generation, if anywhere
+    pub checkpoint: Option<PathBuf>,
+    // What the candidates play against. Greedy unless told otherwise
+    opponent: Box<dyn Fn(usize, u64) -> Box<dyn Strategy>>,
+    rng: StdRng,
+}
+
+impl Tuner {
+    pub fn new(rules: &RuleSet, config: TunerConfig, start: HeuristicParams, seed: u64) -> Self {
+        Tuner {
+            rules: rules.clone(),
+            config,
+            params: start,
+            step: config.step,
+            history: vec![],
+            checkpoint: None,
+            opponent: Box::new(|_, _| Box::new(GreedyStrategy)),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn with_checkpoint(mut self, path: PathBuf) -> Self {
+        self.checkpoint = Some(path);
+        self
+    }
+
+    // Gets the seat and the match seed
+    pub fn with_opponent<F>(mut self, opponent: F) -> Self
+    where
+        F: Fn(usize, u64) -> Box<dyn Strategy> + 'static,
+    {
+        self.opponent = Box::new(opponent);
+        self
+    }
+
+    pub fn run(&mut self) -> io::Result<HeuristicParams> {
+        for _ in 0..self.config.generations {
+            self.step_generation()?;
+        }
+        Ok(self.params)
+    }
+
+    // Too many players for the rules, or no candidates or matches to judge
+    // them by, is an InvalidInput error
+    pub fn step_generation(&mut self) -> io::Result<&Generation> {
+        game::check_players(self.config.num_players, &self.rules)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        if self.config.population == 0 || self.config.matches == 0 {
+            let message = "The tuner needs a population and matches of at least 1";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+        let seeds: Vec<u64> = (0..self.config.matches).map(|_| self.rng.gen()).collect();
+        let centre = self.params.to_vec();
+
+        let mut candidates: Vec<(f64, Vec<f64>)> = vec![];
+        for i in 0..self.config.population {
+            // Always try the current parameters as they are too
+            let values: Vec<f64> = if i == 0 {
+                centre.clone()
+            } else {
+                centre
+                    .iter()
+                    .map(|value| value + self.step * scale(*value) * standard_normal(&mut self.rng))
+                    .collect()
+            };
+            let fitness = self.fitness(&HeuristicParams::from_slice(&values), &seeds);
+            candidates.push((fitness, values));
+        }
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let parents = &candidates[..self.config.parents.clamp(1, candidates.len())];
+        let mut mean = vec![0.0; centre.len()];
+        for (_, values) in parents.iter() {
+            for (total, value) in mean.iter_mut().zip(values.iter()) {
+                *total += value / parents.len() as f64;
+            }
+        }
+
+        self.params = HeuristicParams::from_slice(&mean);
+        self.step *= self.config.step_decay;
+        self.history.push(Generation {
+            params: self.params,
+            fitness: parents.iter().map(|(f, _)| f).sum::<f64>() / parents.len() as f64,
+            best_candidate: HeuristicParams::from_slice(&candidates[0].1),
+            best_fitness: candidates[0].0,
+        });
+
+        if let Some(path) = &self.checkpoint {
+            let generation = self.history.last().unwrap();
+            let text = format!(
+                "# generation {}, fitness {:.2}\n{}",
+                self.history.len(),
+                generation.fitness,
+                self.params.to_text(),
+            );
+            std::fs::write(path, text)?;
+        }
+        Ok(self.history.last().unwrap())
+    }
+
+    // Average points per match ahead of the rest of the table
+    pub fn fitness(&self, params: &HeuristicParams, seeds: &[u64]) -> f64 {
+        let num_players = self.config.num_players;
+        let mut total = 0.0;
+        for (i, seed) in seeds.iter().enumerate() {
+            let seat = i % num_players;
+            let mut strategies: Vec<Box<dyn Strategy>> = (0..num_players)
+                .map(|s| {
+                    if s == seat {
+                        Box::new(HeuristicStrategy::new(*params))
+                    } else {
+                        (self.opponent)(s, *seed)
+                    }
+                })
+                .collect();
+            let mut game_match = Match::new(num_players, &self.rules, self.config.match_config, *seed)
+                .expect("Invalid game state: too many players for the rules");
+            game_match.play(&mut strategies);
+            total += margin(&game_match.scores, seat);
+        }
+        total / seeds.len() as f64
+    }
+}
+
+// Parameters near 0 still get tweaked by a sensible amount
+fn scale(value: f64) -> f64 {
+    value.abs().max(1.0)
+}
+
+// Box-Muller
+fn standard_normal(rng: &mut StdRng) -> f64 {
+    let u: f64 = rng.gen_range(f64::EPSILON..1.0);
+    let v: f64 = rng.gen();
+    (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tiny() -> TunerConfig {
+        TunerConfig {
+            population: 3,
+            parents: 2,
+            generations: 1,
+            matches: 2,
+            match_config: MatchConfig { max_rounds: 1, ..MatchConfig::default() },
+            ..TunerConfig::default()
+        }
+    }
+
+    fn tuner(config: TunerConfig, seed: u64) -> Tuner {
+        Tuner::new(&RuleSet::default(), config, HeuristicParams::default(), seed)
+    }
+
+    #[test]
+    fn same_seed_same_generation() {
+        let mut first = tuner(tiny(), 8);
+        let mut second = tuner(tiny(), 8);
+        assert_eq!(first.run().unwrap(), second.run().unwrap());
+        assert_eq!(first.history.len(), 1);
+        assert_eq!(first.history[0].best_candidate, second.history[0].best_candidate);
+        assert_eq!(first.history[0].fitness, second.history[0].fitness);
+        assert!((first.step - tiny().step * tiny().step_decay).abs() < 1e-12);
+    }
+
+    #[test]
+    fn checkpoint_is_written_every_generation() {
+        let path = std::env::temp_dir().join(format!("rummy-sim-tuner-{}.txt", std::process::id()));
+        let mut tuner = tuner(tiny(), 8).with_checkpoint(path.clone());
+        let params = tuner.run().unwrap();
+        let text = std::fs::read_to_string(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let text = text.unwrap();
+        assert!(text.starts_with("# generation 1, fitness "));
+        assert_eq!(HeuristicParams::from_text(&text), Ok(params));
+    }
+
+    #[test]
+    fn configs_with_nothing_to_judge_are_errors() {
+        for config in [
+            TunerConfig { population: 0, ..tiny() },
+            TunerConfig { matches: 0, ..tiny() },
+            TunerConfig { num_players: 1, ..tiny() },
+        ] {
+            let error = tuner(config, 8).step_generation().err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
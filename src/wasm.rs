@@ -0,0 +1,15 @@
+use wasm_bindgen::prelude::*;
+
+use crate::json;
+
+// Browser entry point. Takes the same position JSON as json::plays_for_position
+// and hands back the plays as a JSON string (or throws with what was wrong)
+//
+// Build with:
+// cargo rustc --lib --target wasm32-unknown-unknown --features wasm --crate-type cdylib
+// then run wasm-bindgen on the output (the cdylib's only asked for here, so
+// native builds don't make one)
+#[wasm_bindgen(js_name = allPossiblePlays)]
+pub fn all_possible_plays(position_json: &str) -> Result<String, JsError> {
+    json::plays_for_position(position_json).map_err(|e| JsError::new(&e))
+}
@@ -0,0 +1,128 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+
+// Runs the real server binary on a free localhost port and talks plain HTTP
+// to it, the same as any other tool would
+
+struct TestServer {
+    child: Child,
+    address: String,
+}
+
+impl TestServer {
+    fn start() -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_server"))
+            .arg("127.0.0.1:0")
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Couldn't start the server");
+
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap()).read_line(&mut line).unwrap();
+        let address = line
+            .trim()
+            .strip_prefix("Listening on http://")
+            .unwrap_or_else(|| panic!("Unexpected first line from the server: {:?}", line))
+            .to_string();
+        TestServer { child, address }
+    }
+
+    // Status code and body
+    fn request(&self, method: &str, path: &str, body: &str) -> (u16, serde_json::Value) {
+        let mut stream = TcpStream::connect(&self.address).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            method,
+            path,
+            self.address,
+            body.len(),
+            body,
+        )
+        .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").expect("No body in response");
+        let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+const POSITION: &str = r#"{
+    "hand": [
+        {"suit": "Spades", "value": "Ace"},
+        {"suit": "Spades", "value": "Two"},
+        {"suit": "Spades", "value": "Three"}
+    ],
+    "discard_pile": [],
+    "played_cards": {"straight_flush_played": [], "multiple_played": []}
+}"#;
+
+#[test]
+fn plays_route_lists_plays() {
+    let server = TestServer::start();
+    let (status, body) = server.request("POST", "/plays", POSITION);
+    assert_eq!(status, 200);
+    let plays = body.as_array().unwrap();
+    assert_eq!(plays.len(), 1);
+    assert_eq!(plays[0]["cards_used"].as_array().unwrap().len(), 3);
+}
+
+#[test]
+fn validate_route_reports_problems() {
+    let server = TestServer::start();
+    let (status, body) = server.request("POST", "/validate", POSITION);
+    assert_eq!(status, 200);
+    assert_eq!(body["valid"], true);
+
+    let impossible = r#"{
+        "hand": [{"suit": "Spades", "value": "Ace"}],
+        "discard_pile": [{"suit": "Spades", "value": "Ace"}],
+        "played_cards": {"straight_flush_played": [], "multiple_played": []}
+    }"#;
+    let (status, body) = server.request("POST", "/validate", impossible);
+    assert_eq!(status, 200);
+    assert_eq!(body["valid"], false);
+    assert!(body["error"].as_str().unwrap().contains("more than once"));
+
+    let (status, _) = server.request("POST", "/validate", "not json");
+    assert_eq!(status, 400);
+}
+
+#[test]
+fn simulate_route_returns_a_report() {
+    let server = TestServer::start();
+    let request = r#"{"strategies": ["greedy", "heuristic"], "num_matches": 3, "seed": 7}"#;
+    let (status, body) = server.request("POST", "/simulate", request);
+    assert_eq!(status, 200);
+    assert_eq!(body["matches"], 3);
+    let seats = body["seats"].as_array().unwrap();
+    assert_eq!(seats.len(), 2);
+    assert_eq!(seats[1]["strategy"], "heuristic");
+    let total_wins: f64 = seats.iter().map(|seat| seat["wins"].as_f64().unwrap()).sum();
+    assert!((total_wins - 3.0).abs() < 1e-9);
+
+    // Same seed, same report
+    let (_, again) = server.request("POST", "/simulate", request);
+    assert_eq!(body, again);
+
+    let (status, body) = server.request("POST", "/simulate", r#"{"strategies": ["greedy", "nope"], "num_matches": 1}"#);
+    assert_eq!(status, 400);
+    assert!(body["error"].as_str().unwrap().contains("Unknown strategy"));
+}
+
+#[test]
+fn unknown_routes_and_methods() {
+    let server = TestServer::start();
+    assert_eq!(server.request("POST", "/nowhere", "").0, 404);
+    assert_eq!(server.request("GET", "/plays", "").0, 405);
+}